@@ -0,0 +1,37 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use search_system::RecGraph;
+use std::hint::black_box;
+
+const NODES: usize = 20_000;
+const AVG_DEGREE: usize = 16;
+
+fn edge_list() -> Vec<(usize, usize)> {
+    (0..NODES)
+        .flat_map(|a| (1..=AVG_DEGREE / 2).map(move |step| (a, (a * 31 + step * 7919) % NODES)))
+        .collect()
+}
+
+fn bench_edge_loading(c: &mut Criterion) {
+    let edges = edge_list();
+    let mut group = c.benchmark_group("graph_loading");
+    group.sample_size(20);
+
+    group.bench_function("unhinted", |b| {
+        b.iter_batched(RecGraph::new, |mut graph| {
+            graph.add_edges(black_box(&edges));
+            graph
+        }, BatchSize::LargeInput)
+    });
+
+    group.bench_function("capacity_hint", |b| {
+        b.iter_batched(|| RecGraph::with_capacity(NODES, AVG_DEGREE), |mut graph| {
+            graph.add_edges(black_box(&edges));
+            graph
+        }, BatchSize::LargeInput)
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_edge_loading);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use search_system::{BTreeIndex, OrderedIndex, SortedVecIndex};
+use std::hint::black_box;
+
+const KEYS: usize = 100_000;
+const KINDS: [&str; 8] = ["notebook", "monitor", "camiseta", "racao", "capa", "mouse", "cadeira", "teclado"];
+
+fn keys() -> Vec<String> {
+    (0..KEYS).map(|i| format!("{} modelo {:06}", KINDS[i % KINDS.len()], (i * 7919) % KEYS)).collect()
+}
+
+fn load<I: OrderedIndex + Default>(keys: &[String]) -> I {
+    let mut sorted: Vec<(usize, &String)> = keys.iter().enumerate().collect();
+    sorted.sort_by_key(|(_, k)| *k);
+    let mut index = I::default();
+    for (id, key) in sorted {
+        index.insert(key, id);
+    }
+    index
+}
+
+fn bench_backend<I: OrderedIndex + Default>(c: &mut Criterion, name: &str, keys: &[String]) {
+    let index: I = load(keys);
+    let mut group = c.benchmark_group(format!("ordered_index/{}", name));
+
+    group.bench_function("prefix", |b| {
+        b.iter(|| {
+            for kind in KINDS {
+                black_box(index.prefix(black_box(&format!("{} modelo 05", kind)), 20));
+            }
+        })
+    });
+
+    group.bench_function("range", |b| {
+        b.iter(|| black_box(index.range(black_box("camiseta"), black_box("capa"), 500)))
+    });
+
+    group.finish();
+}
+
+fn bench_ordered_index(c: &mut Criterion) {
+    let keys = keys();
+    bench_backend::<BTreeIndex>(c, "btree", &keys);
+    bench_backend::<SortedVecIndex>(c, "sorted_vec", &keys);
+}
+
+criterion_group!(benches, bench_ordered_index);
+criterion_main!(benches);
@@ -0,0 +1,3120 @@
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use regex::Regex;
+
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+const TIERED_FUZZY_DISTANCE: usize = 1;
+const DEFAULT_TRENDING_SIZE: usize = 10;
+const DEFAULT_PROMOTION_MULTIPLIER: f32 = 1.5;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Product {
+    #[serde(default)]
+    pub id: usize,
+    pub name: String,
+    pub brand: String,
+    pub category: String,
+    pub description: Option<String>,
+    #[serde(default = "default_true")]
+    pub recommendable: bool,
+    #[serde(default)]
+    pub group_id: Option<String>,
+    #[serde(default)]
+    pub lang: Option<String>,
+    #[serde(default)]
+    pub price: Option<f64>,
+    #[serde(default)]
+    pub rating: f32,
+    #[serde(default)]
+    pub rating_count: u32,
+    #[serde(default)]
+    pub on_promotion: bool,
+}
+
+impl Default for Product {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            name: String::new(),
+            brand: String::new(),
+            category: String::new(),
+            description: None,
+            recommendable: true,
+            group_id: None,
+            lang: None,
+            price: None,
+            rating: 0.0,
+            rating_count: 0,
+            on_promotion: false,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Product {
+    pub fn field(&self, field: Field) -> Option<&str> {
+        match field {
+            Field::Name => Some(&self.name),
+            Field::Brand => Some(&self.brand),
+            Field::Category => Some(&self.category),
+            Field::Description => self.description.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    Name,
+    Brand,
+    Category,
+    Description,
+}
+
+impl Field {
+    pub const ALL: [Field; 4] = [Field::Name, Field::Brand, Field::Category, Field::Description];
+
+    pub fn weight(self) -> f32 {
+        match self {
+            Field::Name => 3.0,
+            Field::Brand => 2.0,
+            Field::Category => 1.5,
+            Field::Description => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stemmer {
+    None,
+    Portuguese,
+    English,
+}
+
+impl Stemmer {
+    fn stem(self, token: &str) -> String {
+        match self {
+            Stemmer::None => token.to_string(),
+            Stemmer::Portuguese => stem_portuguese(token),
+            Stemmer::English => stem_english(token),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LanguageRules {
+    pub stop_words: HashSet<String>,
+    pub stemmer: Stemmer,
+}
+
+impl LanguageRules {
+    pub fn portuguese() -> Self {
+        Self {
+            stop_words: ["a", "o", "e", "de", "da", "do", "das", "dos", "para", "com", "em", "sem"]
+                .into_iter().map(String::from).collect(),
+            stemmer: Stemmer::Portuguese,
+        }
+    }
+
+    pub fn english() -> Self {
+        Self {
+            stop_words: ["a", "an", "the", "and", "of", "for", "with", "in", "to"]
+                .into_iter().map(String::from).collect(),
+            stemmer: Stemmer::English,
+        }
+    }
+}
+
+/// One step of the per-token normalization pipeline. Steps run in the order
+/// given by `TokenizerConfig::pipeline`, after the text is split into words;
+/// a step runs exactly when it is listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizeStep {
+    Lowercase,
+    FoldAccents,
+    RemoveStopWords,
+    Stem,
+    DropNumbers,
+}
+
+impl NormalizeStep {
+    /// Lowercase, drop stop words, then stem. Stop words are matched
+    /// against the unstemmed word, so moving `Stem` before `RemoveStopWords`
+    /// also drops words whose stem is a stop word, and moving `Lowercase`
+    /// later makes stop words case-sensitive. Accents are kept; see
+    /// `TokenizerConfig::with_accent_folding`.
+    pub const DEFAULT_PIPELINE: [NormalizeStep; 3] = [
+        NormalizeStep::Lowercase,
+        NormalizeStep::RemoveStopWords,
+        NormalizeStep::Stem,
+    ];
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenizerConfig {
+    pub stop_words: HashSet<String>,
+    pub default_language: String,
+    pub languages: HashMap<String, LanguageRules>,
+    pub pipeline: Vec<NormalizeStep>,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        Self {
+            stop_words: HashSet::new(),
+            default_language: "pt".into(),
+            languages: HashMap::new(),
+            pipeline: NormalizeStep::DEFAULT_PIPELINE.to_vec(),
+        }
+    }
+}
+
+impl TokenizerConfig {
+    pub fn with_language(mut self, lang: &str, rules: LanguageRules) -> Self {
+        self.languages.insert(lang.to_lowercase(), rules);
+        self
+    }
+
+    /// Adds `FoldAccents` right after `Lowercase` (or first, without it)
+    /// unless the pipeline already folds.
+    pub fn with_accent_folding(mut self) -> Self {
+        if !self.fold_accents() {
+            let pos = self.pipeline.iter().position(|s| *s == NormalizeStep::Lowercase).map_or(0, |i| i + 1);
+            self.pipeline.insert(pos, NormalizeStep::FoldAccents);
+        }
+        self
+    }
+
+    /// Whether the pipeline folds accents.
+    pub fn fold_accents(&self) -> bool {
+        self.pipeline.contains(&NormalizeStep::FoldAccents)
+    }
+}
+
+struct Tokenizer {
+    config: TokenizerConfig,
+    splitter: Regex,
+}
+
+impl Tokenizer {
+    fn new(mut config: TokenizerConfig) -> Self {
+        let fold = config.fold_accents();
+        let normalize = |words: &HashSet<String>| -> HashSet<String> {
+            words.iter()
+                .map(|w| if fold { fold_accents(&w.to_lowercase()) } else { w.to_lowercase() })
+                .collect()
+        };
+        config.stop_words = normalize(&config.stop_words);
+        for rules in config.languages.values_mut() {
+            rules.stop_words = normalize(&rules.stop_words);
+        }
+        config.default_language = config.default_language.to_lowercase();
+        Self { config, splitter: Regex::new(r"[^\w]+").unwrap() }
+    }
+
+    fn language(&self, lang: Option<&str>) -> String {
+        lang.map_or_else(|| self.config.default_language.clone(), str::to_lowercase)
+    }
+
+    fn tokenize(&self, s: &str) -> Vec<String> {
+        self.tokenize_lang(s, None)
+    }
+
+    fn tokenize_lang(&self, s: &str, lang: Option<&str>) -> Vec<String> {
+        let rules = self.config.languages.get(&self.language(lang));
+        let mut tokens: Vec<String> = self.splitter.split(s)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect();
+
+        for step in &self.config.pipeline {
+            match step {
+                NormalizeStep::Lowercase => tokens.iter_mut().for_each(|t| *t = t.to_lowercase()),
+                NormalizeStep::FoldAccents => tokens.iter_mut().for_each(|t| *t = fold_accents(t)),
+                NormalizeStep::RemoveStopWords => tokens.retain(|t| {
+                    !self.config.stop_words.contains(t) && rules.is_none_or(|r| !r.stop_words.contains(t))
+                }),
+                NormalizeStep::Stem => {
+                    if let Some(r) = rules {
+                        tokens.iter_mut().for_each(|t| *t = r.stemmer.stem(t));
+                    }
+                }
+                NormalizeStep::DropNumbers => tokens.retain(|t| !t.chars().all(|c| c.is_numeric())),
+            }
+        }
+        tokens
+    }
+
+    fn field_tokens(&self, p: &Product) -> Vec<(Field, Vec<String>)> {
+        Field::ALL.iter()
+            .filter_map(|&f| p.field(f).map(|text| (f, self.tokenize_lang(text, p.lang.as_deref()))))
+            .collect()
+    }
+
+    /// Byte span of the first word in `text` that normalizes to `token`,
+    /// running the same pipeline as indexing.
+    fn find(&self, text: &str, token: &str, lang: Option<&str>) -> Option<(usize, usize)> {
+        let mut start = 0;
+        let separators = self.splitter.find_iter(text).map(|m| (m.start(), m.end())).chain([(text.len(), text.len())]);
+        for (end, next) in separators {
+            if start < end && self.tokenize_lang(&text[start..end], lang).iter().any(|t| t == token) {
+                return Some((start, end));
+            }
+            start = next;
+        }
+        None
+    }
+
+    #[cfg(any(test, feature = "invariants"))]
+    fn document_tokens(&self, p: &Product) -> Vec<String> {
+        self.field_tokens(p).into_iter().flat_map(|(_, tokens)| tokens).collect()
+    }
+}
+
+struct HashIndex {
+    tokenizer: Tokenizer,
+    index: HashMap<String, HashSet<usize>>,
+    field_index: HashMap<Field, HashMap<String, HashSet<usize>>>,
+    /// Term dictionary: every indexed term with its postings, in term order.
+    terms: Box<dyn OrderedIndex>,
+    /// Forward map: per-document term frequencies, so scoring never re-tokenizes.
+    doc_terms: HashMap<usize, HashMap<String, u32>>,
+    doc_lengths: HashMap<usize, usize>,
+    total_tokens: usize,
+}
+
+impl HashIndex {
+    fn new(tokenizer: Tokenizer, backend: OrderedBackend) -> Self {
+        Self {
+            tokenizer,
+            index: HashMap::new(),
+            field_index: HashMap::new(),
+            terms: backend.build(),
+            doc_terms: HashMap::new(),
+            doc_lengths: HashMap::new(),
+            total_tokens: 0,
+        }
+    }
+
+    fn index_product(&mut self, p: &Product) {
+        let mut len = 0;
+        let doc_terms = self.doc_terms.entry(p.id).or_default();
+        for (field, tokens) in self.tokenizer.field_tokens(p) {
+            len += tokens.len();
+            let postings = self.field_index.entry(field).or_default();
+            for t in tokens {
+                *doc_terms.entry(t.clone()).or_default() += 1;
+                postings.entry(t.clone()).or_default().insert(p.id);
+                if self.index.entry(t.clone()).or_default().insert(p.id) {
+                    self.terms.insert(&t, p.id);
+                }
+            }
+        }
+        self.total_tokens += len;
+        self.doc_lengths.insert(p.id, len);
+    }
+
+    fn remove_product(&mut self, p: &Product) {
+        for (field, tokens) in self.tokenizer.field_tokens(p) {
+            for t in tokens {
+                remove_posting(&mut self.index, &t, p.id);
+                self.terms.remove(&t, p.id);
+                if let Some(postings) = self.field_index.get_mut(&field) {
+                    remove_posting(postings, &t, p.id);
+                }
+            }
+        }
+        self.doc_terms.remove(&p.id);
+        if let Some(len) = self.doc_lengths.remove(&p.id) {
+            self.total_tokens -= len;
+        }
+    }
+
+    fn doc_count(&self) -> usize {
+        self.doc_lengths.len()
+    }
+
+    fn avg_doc_len(&self) -> f32 {
+        if self.doc_count() == 0 {
+            return 0.0;
+        }
+        self.total_tokens as f32 / self.doc_count() as f32
+    }
+
+    fn idf(&self, token: &str) -> f32 {
+        let n = self.doc_count() as f32;
+        let df = self.index.get(token).map_or(0, |ids| ids.len()) as f32;
+        ((n - df + 0.5) / (df + 0.5) + 1.0).ln()
+    }
+
+    fn bm25(&self, p: &Product, tokens: &[String]) -> f32 {
+        self.bm25_terms(p, tokens).iter().map(|t| t.score).sum()
+    }
+
+    fn term_frequency(&self, id: usize, term: &str) -> u32 {
+        self.doc_terms.get(&id).and_then(|terms| terms.get(term)).copied().unwrap_or(0)
+    }
+
+    fn bm25_terms(&self, p: &Product, tokens: &[String]) -> Vec<TermScore> {
+        let doc_len = self.doc_lengths.get(&p.id).copied().unwrap_or(0);
+        let norm = 1.0 - BM25_B + BM25_B * doc_len as f32 / self.avg_doc_len().max(1.0);
+
+        tokens.iter()
+            .map(|t| {
+                let tf = self.term_frequency(p.id, t) as f32;
+                let idf = self.idf(t);
+                TermScore { term: t.clone(), idf, term_frequency: tf, score: idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * norm) }
+            })
+            .collect()
+    }
+
+    #[cfg(any(test, feature = "invariants"))]
+    fn check_invariants(&self, products: &HashMap<usize, Product>) {
+        let total: usize = products.values().map(|p| self.tokenizer.document_tokens(p).len()).sum();
+        assert_eq!(self.doc_count(), products.len(), "document count drifted");
+        assert_eq!(self.total_tokens, total, "total token count drifted");
+
+        let expected = if products.is_empty() { 0.0 } else { total as f32 / products.len() as f32 };
+        assert!((self.avg_doc_len() - expected).abs() < f32::EPSILON, "average document length drifted");
+
+        let mut keys: Vec<&String> = self.index.keys().collect();
+        keys.sort_unstable();
+        assert!(keys.into_iter().map(String::as_str).eq(self.terms.keys()), "term dictionary drifted");
+
+        assert_eq!(self.doc_terms.len(), products.len(), "forward map document count drifted");
+        for (id, terms) in &self.doc_terms {
+            assert!(products.contains_key(id), "forward map has unknown product {}", id);
+            let len: u32 = terms.values().sum();
+            assert_eq!(self.doc_lengths.get(id).copied(), Some(len as usize), "forward map length of {} drifted", id);
+            for t in terms.keys() {
+                assert!(self.index.get(t).is_some_and(|ids| ids.contains(id)), "forward term {:?} of {} missing from index", t, id);
+            }
+        }
+        for (t, ids) in &self.index {
+            for id in ids {
+                assert!(self.doc_terms.get(id).is_some_and(|terms| terms.contains_key(t)), "indexed term {:?} of {} missing from forward map", t, id);
+            }
+        }
+    }
+
+    fn terms_for(&self, id: usize) -> Vec<String> {
+        let mut terms: Vec<String> = self.doc_terms.get(&id).into_iter().flat_map(|terms| terms.keys()).cloned().collect();
+        terms.sort_unstable();
+        terms
+    }
+
+    fn search_tokens_and(&self, tokens: &[String]) -> Vec<usize> {
+        if tokens.is_empty() {
+            return Vec::new();
+        }
+
+        let Some(sets) = tokens.iter()
+            .map(|t| self.index.get(t))
+            .collect::<Option<Vec<&HashSet<usize>>>>() else { return Vec::new(); };
+
+        let mut result = sets[0].clone();
+        for s in sets.iter().skip(1) {
+            result = result.intersection(s).cloned().collect();
+            if result.is_empty() {
+                break;
+            }
+        }
+        result.into_iter().collect()
+    }
+
+    fn and_score(&self, id: usize, tokens: &[String]) -> f32 {
+        let len = self.doc_lengths.get(&id).copied().unwrap_or(0) as f32;
+        let norm = 1.0 - BM25_B + BM25_B * len / self.avg_doc_len().max(1.0);
+        let unique: HashSet<&String> = tokens.iter().collect();
+
+        let score: f32 = unique.into_iter()
+            .map(|t| {
+                let weight = Field::ALL.iter()
+                    .filter(|&&f| self.field_index.get(&f).and_then(|p| p.get(t)).is_some_and(|ids| ids.contains(&id)))
+                    .map(|f| f.weight())
+                    .fold(0.0, f32::max);
+                self.idf(t) * weight
+            })
+            .sum();
+        score / norm
+    }
+
+    fn search_tokens_or_scored(&self, tokens: &[String]) -> Vec<(usize, f32)> {
+        let unique: HashSet<&String> = tokens.iter().collect();
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        for t in unique {
+            let Some(ids) = self.index.get(t) else { continue; };
+            let idf = self.idf(t);
+            for &id in ids {
+                *scores.entry(id).or_default() += idf;
+            }
+        }
+        scores.into_iter().collect()
+    }
+
+    fn search_fields_and(&self, tokens: &[String], fields: &[Field]) -> Vec<usize> {
+        if tokens.is_empty() {
+            return Vec::new();
+        }
+
+        let mut result: Option<HashSet<usize>> = None;
+        for t in tokens {
+            let matches: HashSet<usize> = fields.iter()
+                .filter_map(|f| self.field_index.get(f)?.get(t))
+                .flat_map(|ids| ids.iter().copied())
+                .collect();
+            let next = match result {
+                Some(acc) => acc.intersection(&matches).copied().collect(),
+                None => matches,
+            };
+            if next.is_empty() {
+                return Vec::new();
+            }
+            result = Some(next);
+        }
+        result.unwrap_or_default().into_iter().collect()
+    }
+
+    /// ANDs the ids `expand` returns for each token.
+    fn search_expanded_and<F>(&self, tokens: &[String], expand: F) -> Vec<usize>
+    where
+        F: Fn(&str) -> HashSet<usize>,
+    {
+        if tokens.is_empty() {
+            return Vec::new();
+        }
+
+        let mut result: Option<HashSet<usize>> = None;
+        for t in tokens {
+            let matches = expand(t);
+            let next = match result {
+                Some(acc) => acc.intersection(&matches).copied().collect(),
+                None => matches,
+            };
+            if next.is_empty() {
+                return Vec::new();
+            }
+            result = Some(next);
+        }
+        result.unwrap_or_default().into_iter().collect()
+    }
+
+    fn search_prefix_and(&self, tokens: &[String]) -> Vec<usize> {
+        self.search_expanded_and(tokens, |t| self.terms.prefix(t, usize::MAX).into_iter().collect())
+    }
+
+    fn search_fuzzy_and(&self, tokens: &[String], distance: FuzzyDistance) -> Vec<usize> {
+        self.search_expanded_and(tokens, |t| {
+            self.terms.keys()
+                .filter(|term| levenshtein(term, t) <= distance.max_for(t))
+                .filter_map(|term| self.index.get(term))
+                .flatten()
+                .copied()
+                .collect()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecommendMode {
+    Degree,
+    Jaccard,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphSummary {
+    pub nodes: usize,
+    pub edges: usize,
+    pub avg_degree: f32,
+    pub max_degree: usize,
+    pub connected: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ReasonCode {
+    Manual,
+    CoPurchase,
+    CoView,
+    Editorial,
+}
+
+#[derive(Debug, Clone)]
+struct EdgeMeta {
+    reasons: HashSet<ReasonCode>,
+    weight: f32,
+}
+
+impl Default for EdgeMeta {
+    fn default() -> Self {
+        Self { reasons: HashSet::new(), weight: 1.0 }
+    }
+}
+
+#[derive(PartialEq)]
+struct PathState {
+    distance: f32,
+    node: usize,
+}
+
+impl Eq for PathState {}
+
+impl Ord for PathState {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance).then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for PathState {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+#[derive(Default)]
+pub struct RecGraph {
+    adj: HashMap<usize, HashSet<usize>>,
+    edges: HashMap<(usize, usize), EdgeMeta>,
+    degree_hint: usize,
+}
+
+impl RecGraph {
+    pub fn new() -> Self {
+        Self { adj: HashMap::new(), edges: HashMap::new(), degree_hint: 0 }
+    }
+
+    pub fn with_capacity(nodes: usize, avg_degree: usize) -> Self {
+        Self {
+            adj: HashMap::with_capacity(nodes),
+            edges: HashMap::with_capacity(nodes * avg_degree / 2),
+            degree_hint: avg_degree,
+        }
+    }
+
+    pub fn add_edge(&mut self, a: usize, b: usize) {
+        self.add_edge_with_reason(a, b, ReasonCode::Manual);
+    }
+
+    pub fn add_edge_with_reason(&mut self, a: usize, b: usize, reason: ReasonCode) {
+        if a == b { return; }
+        let hint = self.degree_hint;
+        self.adj.entry(a).or_insert_with(|| HashSet::with_capacity(hint)).insert(b);
+        self.adj.entry(b).or_insert_with(|| HashSet::with_capacity(hint)).insert(a);
+        self.edges.entry(edge_key(a, b)).or_default().reasons.insert(reason);
+    }
+
+    /// Adds (or reweights) an edge. Higher weights mean stronger ties.
+    /// Reweighting keeps the edge's reasons; only new edges get `Manual`.
+    pub fn add_weighted_edge(&mut self, a: usize, b: usize, weight: f32) {
+        if a == b { return; }
+        if !self.edges.contains_key(&edge_key(a, b)) {
+            self.add_edge(a, b);
+        }
+        if let Some(meta) = self.edges.get_mut(&edge_key(a, b)) {
+            meta.weight = weight;
+        }
+    }
+
+    pub fn edge_weight(&self, a: usize, b: usize) -> Option<f32> {
+        self.edges.get(&edge_key(a, b)).map(|meta| meta.weight)
+    }
+
+    pub fn add_edges(&mut self, edges: &[(usize, usize)]) {
+        self.adj.reserve(edges.len());
+        self.edges.reserve(edges.len());
+        for &(a, b) in edges {
+            self.add_edge(a, b);
+        }
+    }
+
+    pub fn edge_reasons(&self, a: usize, b: usize) -> Vec<ReasonCode> {
+        let mut reasons: Vec<ReasonCode> = self.edges.get(&edge_key(a, b))
+            .map(|meta| meta.reasons.iter().copied().collect())
+            .unwrap_or_default();
+        reasons.sort_unstable();
+        reasons
+    }
+
+    pub fn remove_edge(&mut self, a: usize, b: usize) -> bool {
+        let removed = self.adj.get_mut(&a).is_some_and(|s| s.remove(&b));
+        if removed {
+            self.edges.remove(&edge_key(a, b));
+            if let Some(s) = self.adj.get_mut(&b) {
+                s.remove(&a);
+            }
+            for n in [a, b] {
+                if self.adj.get(&n).is_some_and(|s| s.is_empty()) {
+                    self.adj.remove(&n);
+                }
+            }
+        }
+        removed
+    }
+
+    /// Strongest path between two distinct products. Each edge costs
+    /// `1 / weight`; the returned score is the inverse of the total cost, so
+    /// fewer and stronger edges score higher. Non-positive weights are skipped.
+    pub fn best_weighted_path(&self, from: usize, to: usize) -> Option<(Vec<usize>, f32)> {
+        if from == to || !self.adj.contains_key(&from) || !self.adj.contains_key(&to) {
+            return None;
+        }
+
+        let mut distances: HashMap<usize, f32> = HashMap::new();
+        let mut previous: HashMap<usize, usize> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        distances.insert(from, 0.0);
+        heap.push(PathState { distance: 0.0, node: from });
+
+        while let Some(PathState { distance, node }) = heap.pop() {
+            if node == to {
+                let mut path = vec![to];
+                while let Some(&prev) = previous.get(path.last().unwrap()) {
+                    path.push(prev);
+                }
+                path.reverse();
+                return Some((path, 1.0 / distance));
+            }
+            if distances.get(&node).is_some_and(|&best| distance > best) {
+                continue;
+            }
+            for &next in self.neighbors(node).iter() {
+                let weight = self.edges[&edge_key(node, next)].weight;
+                if weight <= 0.0 || !weight.is_finite() {
+                    continue;
+                }
+                let candidate = distance + 1.0 / weight;
+                if distances.get(&next).is_none_or(|&best| candidate < best) {
+                    distances.insert(next, candidate);
+                    previous.insert(next, node);
+                    heap.push(PathState { distance: candidate, node: next });
+                }
+            }
+        }
+        None
+    }
+
+    pub fn neighbors(&self, id: usize) -> Vec<usize> {
+        let mut out: Vec<usize> = self.adj.get(&id).into_iter().flatten().copied().collect();
+        out.sort_unstable();
+        out
+    }
+
+    pub fn remove_node(&mut self, id: usize) {
+        let Some(neighbors) = self.adj.remove(&id) else { return; };
+        for n in neighbors {
+            self.edges.remove(&edge_key(id, n));
+            if let Some(set) = self.adj.get_mut(&n) {
+                set.remove(&id);
+                if set.is_empty() {
+                    self.adj.remove(&n);
+                }
+            }
+        }
+    }
+
+    pub fn degree(&self, id: usize) -> usize {
+        self.adj.get(&id).map_or(0, |s| s.len())
+    }
+
+    pub fn max_degree(&self) -> usize {
+        self.adj.values().map(|s| s.len()).max().unwrap_or(0)
+    }
+
+    pub fn degree_centrality(&self, id: usize) -> f32 {
+        match self.max_degree() {
+            0 => 0.0,
+            max => self.degree(id) as f32 / max as f32,
+        }
+    }
+
+    pub fn jaccard(&self, a: usize, b: usize) -> f32 {
+        let (Some(na), Some(nb)) = (self.adj.get(&a), self.adj.get(&b)) else { return 0.0; };
+        let union = na.union(nb).count();
+        if union == 0 {
+            return 0.0;
+        }
+        na.intersection(nb).count() as f32 / union as f32
+    }
+
+    pub fn ranked_neighbors(&self, product_id: usize, mode: RecommendMode) -> Vec<usize> {
+        self.scored_neighbors(product_id, mode).into_iter().map(|(id, _)| id).collect()
+    }
+
+    pub fn scored_neighbors(&self, product_id: usize, mode: RecommendMode) -> Vec<(usize, f32)> {
+        let Some(neighbors) = self.adj.get(&product_id) else { return Vec::new(); };
+
+        let mut scored: Vec<(usize, f32)> = neighbors.iter()
+            .map(|&nid| {
+                let score = match mode {
+                    RecommendMode::Degree => self.degree(nid) as f32,
+                    RecommendMode::Jaccard => self.jaccard(product_id, nid),
+                };
+                (nid, score)
+            })
+            .collect();
+
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scored
+    }
+
+    pub fn recommend(&self, product_id: usize, limit: usize) -> Vec<usize> {
+        self.ranked_neighbors(product_id, RecommendMode::Degree).into_iter().take(limit).collect()
+    }
+
+    pub fn connected_components(&self) -> Vec<Vec<usize>> {
+        let mut seen = HashSet::new();
+        let mut starts: Vec<usize> = self.adj.keys().copied().collect();
+        starts.sort_unstable();
+
+        let mut components = Vec::new();
+        for start in starts {
+            if !seen.insert(start) {
+                continue;
+            }
+            let mut component = vec![start];
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                for &n in self.adj.get(&node).into_iter().flatten() {
+                    if seen.insert(n) {
+                        component.push(n);
+                        stack.push(n);
+                    }
+                }
+            }
+            component.sort_unstable();
+            components.push(component);
+        }
+        components
+    }
+
+    pub fn predict_links(&self, limit: usize) -> Vec<(usize, usize, f32)> {
+        let mut scores: HashMap<(usize, usize), f32> = HashMap::new();
+        for neighbors in self.adj.values() {
+            if neighbors.len() < 2 {
+                continue;
+            }
+            let weight = 1.0 / (neighbors.len() as f32).ln();
+            let mut sorted: Vec<usize> = neighbors.iter().copied().collect();
+            sorted.sort_unstable();
+            for (i, &a) in sorted.iter().enumerate() {
+                for &b in &sorted[i + 1..] {
+                    if !self.adj.get(&a).is_some_and(|s| s.contains(&b)) {
+                        *scores.entry((a, b)).or_default() += weight;
+                    }
+                }
+            }
+        }
+
+        let mut predicted: Vec<(usize, usize, f32)> = scores.into_iter().map(|((a, b), score)| (a, b, score)).collect();
+        predicted.sort_by(|x, y| y.2.total_cmp(&x.2).then((x.0, x.1).cmp(&(y.0, y.1))));
+        predicted.truncate(limit);
+        predicted
+    }
+
+    pub fn summary(&self) -> GraphSummary {
+        let nodes = self.adj.len();
+        let edges = self.adj.values().map(|s| s.len()).sum::<usize>() / 2;
+        GraphSummary {
+            nodes,
+            edges,
+            avg_degree: if nodes == 0 { 0.0 } else { 2.0 * edges as f32 / nodes as f32 },
+            max_degree: self.max_degree(),
+            connected: self.connected_components().len() <= 1,
+        }
+    }
+
+    pub fn multi_hop(&self, product_id: usize, max_hops: usize) -> Vec<usize> {
+        self.multi_hop_distances(product_id, max_hops).into_iter().map(|(id, _)| id).collect()
+    }
+
+    /// `(id, hops)` pairs in `multi_hop` order.
+    pub fn multi_hop_distances(&self, product_id: usize, max_hops: usize) -> Vec<(usize, usize)> {
+        let mut dist: HashMap<usize, usize> = HashMap::new();
+        dist.insert(product_id, 0);
+        let mut frontier = vec![product_id];
+
+        for hop in 1..=max_hops {
+            let mut next = Vec::new();
+            for node in frontier {
+                for &n in self.adj.get(&node).into_iter().flatten() {
+                    if !dist.contains_key(&n) {
+                        dist.insert(n, hop);
+                        next.push(n);
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+
+        let mut ranked: Vec<(usize, usize)> = dist.into_iter().filter(|&(id, _)| id != product_id).collect();
+        ranked.sort_by(|a, b| {
+            a.1.cmp(&b.1)
+                .then(self.degree(b.0).cmp(&self.degree(a.0)))
+                .then(a.0.cmp(&b.0))
+        });
+        ranked
+    }
+
+    pub fn ranked_neighbors_by_reason(&self, product_id: usize, reasons: &[ReasonCode]) -> Vec<usize> {
+        self.scored_neighbors_by_reason(product_id, reasons).into_iter().map(|(id, _)| id).collect()
+    }
+
+    pub fn scored_neighbors_by_reason(&self, product_id: usize, reasons: &[ReasonCode]) -> Vec<(usize, f32)> {
+        self.scored_neighbors(product_id, RecommendMode::Degree).into_iter()
+            .filter(|&(n, _)| {
+                self.edges.get(&edge_key(product_id, n))
+                    .is_some_and(|meta| reasons.iter().any(|r| meta.reasons.contains(r)))
+            })
+            .collect()
+    }
+
+    pub fn recommend_by_reason(&self, product_id: usize, limit: usize, reasons: &[ReasonCode]) -> Vec<usize> {
+        self.ranked_neighbors_by_reason(product_id, reasons).into_iter().take(limit).collect()
+    }
+
+    pub fn recommend_jaccard(&self, product_id: usize, limit: usize) -> Vec<usize> {
+        self.ranked_neighbors(product_id, RecommendMode::Jaccard).into_iter().take(limit).collect()
+    }
+}
+
+/// Ordered `key -> ids` storage backing the name index and the term
+/// dictionary. Implementations must return ids in key order, then insertion
+/// order, and drop a key once its last id is removed.
+pub trait OrderedIndex: Send + Sync {
+    fn insert(&mut self, key: &str, id: usize);
+    fn remove(&mut self, key: &str, id: usize);
+    fn keys(&self) -> Box<dyn Iterator<Item = &str> + '_>;
+    fn prefix(&self, prefix: &str, limit: usize) -> Vec<usize>;
+    /// Ids for keys in `[from, to)`.
+    fn range(&self, from: &str, to: &str, limit: usize) -> Vec<usize>;
+}
+
+/// Which `OrderedIndex` a catalog uses for names and indexed terms. `BTree`
+/// is the default because it handles mutation cheaply. `SortedVec` keeps keys
+/// contiguous, but each insert or remove shifts the vector, so it mainly
+/// suits static catalogs; `benches/ordered_index.rs` compares the two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrderedBackend {
+    #[default]
+    BTree,
+    SortedVec,
+}
+
+impl OrderedBackend {
+    fn build(self) -> Box<dyn OrderedIndex> {
+        match self {
+            OrderedBackend::BTree => Box::new(BTreeIndex::default()),
+            OrderedBackend::SortedVec => Box::new(SortedVecIndex::default()),
+        }
+    }
+}
+
+fn take_ids<'a>(postings: impl Iterator<Item = &'a Vec<usize>>, limit: usize) -> Vec<usize> {
+    postings.flatten().copied().take(limit).collect()
+}
+
+#[derive(Default)]
+pub struct BTreeIndex {
+    tree: BTreeMap<String, Vec<usize>>,
+}
+
+impl OrderedIndex for BTreeIndex {
+    fn insert(&mut self, key: &str, id: usize) {
+        self.tree.entry(key.to_string()).or_default().push(id);
+    }
+
+    fn remove(&mut self, key: &str, id: usize) {
+        if let Some(ids) = self.tree.get_mut(key) {
+            ids.retain(|&x| x != id);
+            if ids.is_empty() {
+                self.tree.remove(key);
+            }
+        }
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.tree.keys().map(String::as_str))
+    }
+
+    fn prefix(&self, prefix: &str, limit: usize) -> Vec<usize> {
+        let postings = self.tree.range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .map(|(_, ids)| ids);
+        take_ids(postings, limit)
+    }
+
+    fn range(&self, from: &str, to: &str, limit: usize) -> Vec<usize> {
+        if from >= to {
+            return Vec::new();
+        }
+        take_ids(self.tree.range::<str, _>((Bound::Included(from), Bound::Excluded(to))).map(|(_, ids)| ids), limit)
+    }
+}
+
+#[derive(Default)]
+pub struct SortedVecIndex {
+    entries: Vec<(String, Vec<usize>)>,
+}
+
+impl SortedVecIndex {
+    fn lower_bound(&self, key: &str) -> usize {
+        self.entries.partition_point(|(k, _)| k.as_str() < key)
+    }
+}
+
+impl OrderedIndex for SortedVecIndex {
+    fn insert(&mut self, key: &str, id: usize) {
+        let pos = self.lower_bound(key);
+        match self.entries.get_mut(pos) {
+            Some((k, ids)) if k == key => ids.push(id),
+            _ => self.entries.insert(pos, (key.to_string(), vec![id])),
+        }
+    }
+
+    fn remove(&mut self, key: &str, id: usize) {
+        let pos = self.lower_bound(key);
+        if let Some((k, ids)) = self.entries.get_mut(pos) && k == key {
+            ids.retain(|&x| x != id);
+            if ids.is_empty() {
+                self.entries.remove(pos);
+            }
+        }
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.entries.iter().map(|(k, _)| k.as_str()))
+    }
+
+    fn prefix(&self, prefix: &str, limit: usize) -> Vec<usize> {
+        let postings = self.entries[self.lower_bound(prefix)..].iter()
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .map(|(_, ids)| ids);
+        take_ids(postings, limit)
+    }
+
+    fn range(&self, from: &str, to: &str, limit: usize) -> Vec<usize> {
+        if from >= to {
+            return Vec::new();
+        }
+        let (start, end) = (self.lower_bound(from), self.lower_bound(to));
+        take_ids(self.entries[start..end].iter().map(|(_, ids)| ids), limit)
+    }
+}
+
+struct NameIndex {
+    backend: OrderedBackend,
+    store: Box<dyn OrderedIndex>,
+}
+
+impl NameIndex {
+    fn new(backend: OrderedBackend) -> Self {
+        Self { backend, store: backend.build() }
+    }
+
+    fn insert(&mut self, name: &str, id: usize) {
+        self.store.insert(&name.to_lowercase(), id);
+    }
+
+    fn remove(&mut self, name: &str, id: usize) {
+        self.store.remove(&name.to_lowercase(), id);
+    }
+
+    fn search_prefix(&self, prefix: &str, limit: usize) -> Vec<usize> {
+        self.store.prefix(&prefix.to_lowercase(), limit)
+    }
+
+    fn search_range(&self, from: &str, to: &str, limit: usize) -> Vec<usize> {
+        self.store.range(&from.to_lowercase(), &to.to_lowercase(), limit)
+    }
+}
+
+/// Edit-distance budget for fuzzy matching. `Proportional` allows
+/// `len / factor` edits per query token, so short tokens must match exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FuzzyDistance {
+    Fixed(usize),
+    Proportional { factor: usize },
+}
+
+impl FuzzyDistance {
+    pub fn max_for(self, token: &str) -> usize {
+        match self {
+            FuzzyDistance::Fixed(max) => max,
+            FuzzyDistance::Proportional { factor } => token.chars().count() / factor.max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchTier {
+    Exact,
+    Prefix,
+    Fuzzy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relaxation {
+    None,
+    DroppedTokens(usize),
+    Prefix,
+    Fuzzy,
+    /// Every strategy came back empty (or the query had no tokens).
+    Exhausted,
+}
+
+/// Bayesian rating: `(prior_weight * prior_mean + rating * count) /
+/// (prior_weight + count)`, so a handful of reviews barely moves a product
+/// off the prior. Blended with normalized relevance by the two weights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RatingConfig {
+    pub prior_mean: f32,
+    pub prior_weight: f32,
+    pub relevance_weight: f32,
+    pub rating_weight: f32,
+}
+
+impl Default for RatingConfig {
+    fn default() -> Self {
+        Self { prior_mean: 3.5, prior_weight: 10.0, relevance_weight: 0.5, rating_weight: 0.5 }
+    }
+}
+
+/// Why a recommendation call came back empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecommendDiagnostic {
+    GraphEmpty,
+    SeedMissing,
+    SeedIsolated,
+    NoCandidates,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TermScore {
+    pub term: String,
+    pub idf: f32,
+    pub term_frequency: f32,
+    pub score: f32,
+}
+
+/// How `search_ranked` scored one product: the per-term BM25 contributions
+/// plus the promotion boost add up to `total`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScoreBreakdown {
+    pub terms: Vec<TermScore>,
+    pub promotion_boost: f32,
+    pub total: f32,
+}
+
+/// What `search` does when a query has no tokens left after normalization
+/// (blank, punctuation or only stop words). `All` still honors the limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyQueryPolicy {
+    #[default]
+    Empty,
+    All,
+    Error,
+}
+
+#[derive(Debug)]
+pub struct SearchResult<'a> {
+    pub products: Vec<&'a Product>,
+    pub total: usize,
+    pub elapsed: Duration,
+    pub truncated: bool,
+}
+
+#[derive(Debug)]
+pub enum CatalogError {
+    Parse { line: usize, reason: String },
+    Invalid { line: usize, reason: String },
+    Io(std::io::Error),
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatalogError::Parse { line, reason } => write!(f, "parse error on line {}: {}", line, reason),
+            CatalogError::Invalid { line, reason } => write!(f, "invalid record on line {}: {}", line, reason),
+            CatalogError::Io(e) => write!(f, "storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CatalogError {}
+
+impl From<std::io::Error> for CatalogError {
+    fn from(e: std::io::Error) -> Self {
+        CatalogError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for CatalogError {
+    fn from(e: serde_json::Error) -> Self {
+        CatalogError::Parse { line: e.line(), reason: e.to_string() }
+    }
+}
+
+/// Serializable catalog state: products, graph edges, pins and popularity.
+/// Tokenizer settings, caches and the query log are not part of it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CatalogSnapshot {
+    pub next_id: usize,
+    pub products: Vec<Product>,
+    pub edges: Vec<(usize, usize, Vec<ReasonCode>)>,
+    #[serde(default)]
+    pub edge_weights: Vec<(usize, usize, f32)>,
+    pub pins: Vec<(String, Vec<usize>)>,
+    pub popularity: Vec<(usize, usize)>,
+}
+
+pub trait CatalogStore {
+    fn save_snapshot(&self, snapshot: &CatalogSnapshot) -> Result<(), CatalogError>;
+    fn load_snapshot(&self) -> Result<CatalogSnapshot, CatalogError>;
+}
+
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl CatalogStore for FileStore {
+    fn save_snapshot(&self, snapshot: &CatalogSnapshot) -> Result<(), CatalogError> {
+        fs::write(&self.path, serde_json::to_string(snapshot)?)?;
+        Ok(())
+    }
+
+    fn load_snapshot(&self) -> Result<CatalogSnapshot, CatalogError> {
+        Ok(serde_json::from_str(&fs::read_to_string(&self.path)?)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryNode {
+    Term(String),
+    And(Vec<QueryNode>),
+    Or(Vec<QueryNode>),
+    Not(Box<QueryNode>),
+    Boost(Box<QueryNode>, f32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryError {
+    Empty,
+    UnexpectedToken(String),
+    /// An `OR` or `NOT` with nothing to apply to.
+    MissingOperand(String),
+    UnbalancedParen,
+    InvalidBoost { term: String, value: String },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Empty => write!(f, "query has no searchable terms"),
+            QueryError::UnexpectedToken(token) => write!(f, "unexpected token '{}'", token),
+            QueryError::MissingOperand(op) => write!(f, "'{}' is missing an operand", op),
+            QueryError::UnbalancedParen => write!(f, "unbalanced parentheses"),
+            QueryError::InvalidBoost { term, value } => {
+                write!(f, "invalid boost '{}' on '{}': expected a positive number", value, term)
+            }
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum QueryToken {
+    Word(String),
+    Open,
+    Close,
+    Caret,
+}
+
+impl fmt::Display for QueryToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryToken::Word(w) => f.write_str(w),
+            QueryToken::Open => f.write_str("("),
+            QueryToken::Close => f.write_str(")"),
+            QueryToken::Caret => f.write_str("^"),
+        }
+    }
+}
+
+fn lex_query(query: &str) -> Vec<QueryToken> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    for c in query.chars() {
+        let token = match c {
+            '(' => Some(QueryToken::Open),
+            ')' => Some(QueryToken::Close),
+            '^' => Some(QueryToken::Caret),
+            c if c.is_whitespace() => None,
+            c => {
+                word.push(c);
+                continue;
+            }
+        };
+        if !word.is_empty() {
+            tokens.push(QueryToken::Word(std::mem::take(&mut word)));
+        }
+        tokens.extend(token);
+    }
+    if !word.is_empty() {
+        tokens.push(QueryToken::Word(word));
+    }
+    tokens
+}
+
+struct QueryParser<'a> {
+    tokenizer: &'a Tokenizer,
+    tokens: Vec<QueryToken>,
+    pos: usize,
+}
+
+impl QueryParser<'_> {
+    fn parse(mut self) -> Result<QueryNode, QueryError> {
+        let node = self.parse_or()?;
+        match self.tokens.get(self.pos) {
+            None => node.ok_or(QueryError::Empty),
+            Some(QueryToken::Close) => Err(QueryError::UnbalancedParen),
+            Some(token) => Err(QueryError::UnexpectedToken(token.to_string())),
+        }
+    }
+
+    fn peek_word(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some(QueryToken::Word(w)) if w == keyword)
+    }
+
+    /// True when nothing that could start an operand follows.
+    fn at_operand_end(&self) -> bool {
+        matches!(self.tokens.get(self.pos), None | Some(QueryToken::Close)) || self.peek_word("OR") || self.peek_word("AND")
+    }
+
+    fn parse_or(&mut self) -> Result<Option<QueryNode>, QueryError> {
+        if self.peek_word("OR") {
+            return Err(QueryError::MissingOperand("OR".into()));
+        }
+        let mut branches: Vec<QueryNode> = self.parse_and()?.into_iter().collect();
+        while self.peek_word("OR") {
+            self.pos += 1;
+            if self.at_operand_end() {
+                return Err(QueryError::MissingOperand("OR".into()));
+            }
+            branches.extend(self.parse_and()?);
+        }
+        Ok(match branches.len() {
+            0 => None,
+            1 => branches.pop(),
+            _ => Some(QueryNode::Or(branches)),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Option<QueryNode>, QueryError> {
+        let mut parts = Vec::new();
+        loop {
+            match self.tokens.get(self.pos) {
+                None | Some(QueryToken::Close) => break,
+                Some(QueryToken::Word(w)) if w == "OR" => break,
+                Some(QueryToken::Word(w)) if w == "AND" => self.pos += 1,
+                _ => parts.extend(self.parse_unary()?),
+            }
+        }
+        Ok(match parts.len() {
+            0 => None,
+            1 => parts.pop(),
+            _ => Some(QueryNode::And(parts)),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Option<QueryNode>, QueryError> {
+        if self.peek_word("NOT") {
+            self.pos += 1;
+            if self.at_operand_end() {
+                return Err(QueryError::MissingOperand("NOT".into()));
+            }
+            return Ok(self.parse_unary()?.map(|node| QueryNode::Not(Box::new(node))));
+        }
+
+        let (node, label) = match self.tokens.get(self.pos).cloned() {
+            Some(QueryToken::Open) => {
+                self.pos += 1;
+                let node = self.parse_or()?;
+                if self.tokens.get(self.pos) != Some(&QueryToken::Close) {
+                    return Err(QueryError::UnbalancedParen);
+                }
+                self.pos += 1;
+                (node, "(...)".to_string())
+            }
+            Some(QueryToken::Word(word)) => {
+                self.pos += 1;
+                let mut terms: Vec<QueryNode> = self.tokenizer.tokenize(&word).into_iter().map(QueryNode::Term).collect();
+                let node = match terms.len() {
+                    0 => None,
+                    1 => terms.pop(),
+                    _ => Some(QueryNode::And(terms)),
+                };
+                (node, word)
+            }
+            Some(QueryToken::Close) => return Err(QueryError::UnbalancedParen),
+            Some(token) => return Err(QueryError::UnexpectedToken(token.to_string())),
+            None => return Ok(None),
+        };
+
+        if self.tokens.get(self.pos) != Some(&QueryToken::Caret) {
+            return Ok(node);
+        }
+        self.pos += 1;
+        let value = match self.tokens.get(self.pos) {
+            Some(QueryToken::Word(value)) => {
+                self.pos += 1;
+                value.clone()
+            }
+            _ => String::new(),
+        };
+        match value.parse::<f32>() {
+            Ok(boost) if boost.is_finite() && boost > 0.0 => Ok(node.map(|n| QueryNode::Boost(Box::new(n), boost))),
+            _ => Err(QueryError::InvalidBoost { term: label, value }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    Strict,
+    Lenient,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedRecord {
+    pub line: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub processed: usize,
+    pub added: usize,
+    pub duplicates: usize,
+    pub rejected: Vec<RejectedRecord>,
+}
+
+struct TopN {
+    capacity: usize,
+    entries: Vec<(usize, usize)>,
+}
+
+impl TopN {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, entries: Vec::with_capacity(capacity) }
+    }
+
+    fn sort(&mut self) {
+        self.entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    }
+
+    /// Applies a score change for `id`, returning `true` when the change may
+    /// have let a product outside the list overtake it and a rebuild is needed.
+    fn update(&mut self, id: usize, score: usize) -> bool {
+        match self.entries.iter().position(|&(e, _)| e == id) {
+            Some(i) => {
+                let old = self.entries[i].1;
+                if score < old {
+                    return true;
+                }
+                self.entries[i].1 = score;
+                self.sort();
+                false
+            }
+            None => {
+                if score == 0 || self.capacity == 0 {
+                    return false;
+                }
+                let beats_last = self.entries.last()
+                    .is_some_and(|&(e, s)| score > s || (score == s && id < e));
+                if self.entries.len() < self.capacity || beats_last {
+                    self.entries.push((id, score));
+                    self.sort();
+                    self.entries.truncate(self.capacity);
+                }
+                false
+            }
+        }
+    }
+
+    fn rebuild(&mut self, scores: Vec<(usize, usize)>) {
+        self.entries = scores.into_iter().filter(|&(_, s)| s > 0).collect();
+        self.sort();
+        self.entries.truncate(self.capacity);
+    }
+
+    fn ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entries.iter().map(|&(id, _)| id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogEvent {
+    Query,
+    View(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryLogEntry {
+    pub query: String,
+    pub result_count: usize,
+    pub timestamp: u64,
+    pub session: Option<String>,
+    pub event: LogEvent,
+}
+
+pub type Clock = Box<dyn Fn() -> u64 + Send + Sync>;
+
+struct QueryLog {
+    capacity: usize,
+    entries: VecDeque<QueryLogEntry>,
+    clock: Clock,
+    session: Option<String>,
+}
+
+impl QueryLog {
+    fn new(capacity: usize, clock: Clock) -> Self {
+        Self { capacity, entries: VecDeque::with_capacity(capacity), clock, session: None }
+    }
+
+    fn record(&mut self, query: String, result_count: usize, event: LogEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        let timestamp = (self.clock)();
+        let session = self.session.clone();
+        self.entries.push_back(QueryLogEntry { query, result_count, timestamp, session, event });
+    }
+}
+
+struct SimilarityMatrix {
+    top_k: usize,
+    neighbors: HashMap<usize, Vec<usize>>,
+    rows_computed: usize,
+}
+
+pub struct Catalog {
+    products: HashMap<usize, Product>,
+    next_id: usize,
+    hash_index: HashIndex,
+    rec_graph: RecGraph,
+    name_tree: NameIndex,
+    categories: HashMap<String, HashSet<usize>>,
+    pins: HashMap<String, Vec<usize>>,
+    rec_cache: Mutex<HashMap<(usize, usize, RecommendMode), Vec<usize>>>,
+    query_log: Mutex<Option<QueryLog>>,
+    popularity: HashMap<usize, usize>,
+    trending: TopN,
+    dedupe_by_name: bool,
+    rating_config: RatingConfig,
+    promote_sales: bool,
+    promotion_multiplier: f32,
+    empty_query_policy: EmptyQueryPolicy,
+    similarity: Option<SimilarityMatrix>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::with_tokenizer(TokenizerConfig::default())
+    }
+
+    pub fn with_capacity(products: usize, avg_degree: usize) -> Self {
+        let mut catalog = Self::new();
+        catalog.products.reserve(products);
+        catalog.rec_graph = RecGraph::with_capacity(products, avg_degree);
+        catalog
+    }
+
+    /// Empty catalog whose name index and term dictionary use `backend`.
+    pub fn with_ordered_backend(backend: OrderedBackend) -> Self {
+        let mut catalog = Self::new();
+        let config = catalog.hash_index.tokenizer.config.clone();
+        catalog.hash_index = HashIndex::new(Tokenizer::new(config), backend);
+        catalog.name_tree = NameIndex::new(backend);
+        catalog
+    }
+
+    pub fn with_tokenizer(config: TokenizerConfig) -> Self {
+        Self {
+            products: HashMap::new(),
+            next_id: 1,
+            hash_index: HashIndex::new(Tokenizer::new(config), OrderedBackend::default()),
+            rec_graph: RecGraph::new(),
+            name_tree: NameIndex::new(OrderedBackend::default()),
+            categories: HashMap::new(),
+            pins: HashMap::new(),
+            rec_cache: Mutex::new(HashMap::new()),
+            query_log: Mutex::new(None),
+            popularity: HashMap::new(),
+            trending: TopN::new(DEFAULT_TRENDING_SIZE),
+            dedupe_by_name: false,
+            rating_config: RatingConfig::default(),
+            promote_sales: false,
+            promotion_multiplier: DEFAULT_PROMOTION_MULTIPLIER,
+            empty_query_policy: EmptyQueryPolicy::default(),
+            similarity: None,
+        }
+    }
+
+    pub fn add_product(&mut self, mut p: Product) {
+        p.id = self.next_id;
+        self.next_id += 1;
+        #[cfg(feature = "tracing")]
+        let (_span, start) = (tracing::debug_span!("catalog.index", id = p.id).entered(), Instant::now());
+
+        self.hash_index.index_product(&p);
+        self.name_tree.insert(&p.name, p.id);
+        self.categories.entry(self.normalize_key(&p.category)).or_default().insert(p.id);
+        let id = p.id;
+        self.products.insert(id, p);
+
+        // Edges may already point at this id; seeds next to it now see a new candidate.
+        self.invalidate_seeds(&self.affected_seeds(&[id]));
+        self.refresh_trending(&[id]);
+        let related = self.content_related(id);
+        self.refresh_similarity(related);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            tokens = self.hash_index.doc_terms.get(&id).map_or(0, |terms| terms.len()),
+            elapsed_us = start.elapsed().as_micros() as u64,
+            "product indexed"
+        );
+    }
+
+    pub fn remove_product(&mut self, id: usize) -> Option<Product> {
+        // Removing the node changes its neighbors' degrees, which reorders
+        // their neighbors' recommendations too.
+        let seeds = self.affected_seeds(&self.rec_graph.neighbors(id));
+        let related = self.content_related(id);
+        let p = self.products.remove(&id)?;
+        self.hash_index.remove_product(&p);
+        self.name_tree.remove(&p.name, id);
+        let category = self.normalize_key(&p.category);
+        remove_posting(&mut self.categories, &category, id);
+        self.rec_graph.remove_node(id);
+        self.popularity.remove(&id);
+        self.rebuild_trending();
+        self.invalidate_seeds(&seeds);
+        self.refresh_similarity(related);
+        for ids in self.pins.values_mut() {
+            ids.retain(|&x| x != id);
+        }
+        Some(p)
+    }
+
+    pub fn update_product(&mut self, id: usize, mut p: Product) -> bool {
+        let mut related = self.content_related(id);
+        let Some(old) = self.products.remove(&id) else { return false; };
+        self.hash_index.remove_product(&old);
+        self.name_tree.remove(&old.name, id);
+        let category = self.normalize_key(&old.category);
+        remove_posting(&mut self.categories, &category, id);
+
+        p.id = id;
+        self.hash_index.index_product(&p);
+        self.name_tree.insert(&p.name, id);
+        self.categories.entry(self.normalize_key(&p.category)).or_default().insert(id);
+        self.products.insert(id, p);
+        self.invalidate_seeds(&self.affected_seeds(&[id]));
+        related.extend(self.content_related(id));
+        self.refresh_similarity(related);
+        true
+    }
+
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        self.hash_index.tokenizer.tokenize(text)
+    }
+
+    pub fn tokenize_lang(&self, text: &str, lang: Option<&str>) -> Vec<String> {
+        self.hash_index.tokenizer.tokenize_lang(text, lang)
+    }
+
+    fn normalize_key(&self, text: &str) -> String {
+        self.tokenize(text).join(" ")
+    }
+
+    /// Every indexed term in sorted order, maintained incrementally. Prefix
+    /// matching seeks into it and fuzzy matching scans it instead of the
+    /// inverted index keys.
+    pub fn term_dictionary(&self) -> Vec<&str> {
+        self.hash_index.terms.keys().collect()
+    }
+
+    pub fn product_terms(&self, id: usize) -> Vec<String> {
+        self.hash_index.terms_for(id)
+    }
+
+    /// The product's most distinctive terms, scored by TF-IDF against the
+    /// whole catalog. Ties are broken alphabetically.
+    pub fn keywords(&self, product_id: usize, top_n: usize) -> Vec<(String, f32)> {
+        let Some(tf) = self.hash_index.doc_terms.get(&product_id) else { return Vec::new(); };
+        let mut scored: Vec<(String, f32)> = tf.iter()
+            .map(|(t, &n)| (t.clone(), n as f32 * self.hash_index.idf(t)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scored.truncate(top_n);
+        scored
+    }
+
+    /// Products ranked by Jaccard overlap between their indexed terms and
+    /// `tokens`, which are normalized like a query first.
+    pub fn most_similar_to_tokens(&self, tokens: &[String], limit: usize) -> Vec<(&Product, f32)> {
+        let wanted: HashSet<String> = self.tokenize(&tokens.join(" ")).into_iter().collect();
+        let candidates: HashSet<usize> = wanted.iter()
+            .filter_map(|t| self.hash_index.index.get(t))
+            .flatten()
+            .copied()
+            .collect();
+
+        let mut scored: Vec<(&Product, f32)> = candidates.into_iter()
+            .filter_map(|id| self.products.get(&id))
+            .map(|p| {
+                let terms = &self.hash_index.doc_terms[&p.id];
+                let shared = wanted.iter().filter(|t| terms.contains_key(*t)).count();
+                (p, shared as f32 / (terms.len() + wanted.len() - shared) as f32)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.id.cmp(&b.0.id)));
+        scored.truncate(limit);
+        scored
+    }
+
+    pub fn snippet(&self, id: usize, query: &str, radius: usize) -> Option<String> {
+        let p = self.products.get(&id)?;
+        let tokenizer = &self.hash_index.tokenizer;
+        let text = p.description.as_deref().unwrap_or(&p.name);
+
+        let lang = p.lang.as_deref();
+        tokenizer.tokenize_lang(query, lang).iter()
+            .find_map(|t| tokenizer.find(text, t, lang))
+            .map(|(start, end)| {
+                safe_slice(text, start.saturating_sub(radius), end + radius).trim().to_string()
+            })
+    }
+
+    pub fn import_json_lines(&mut self, input: &str, mode: ImportMode) -> Result<ImportReport, CatalogError> {
+        let records = input.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let parsed = serde_json::from_str::<Product>(line).map_err(|e| e.to_string());
+                (i + 1, parsed)
+            })
+            .collect();
+        self.import_records(records, mode)
+    }
+
+    pub fn import_csv(&mut self, input: &str, mode: ImportMode) -> Result<ImportReport, CatalogError> {
+        let mut lines = input.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let Some((_, header)) = lines.next() else { return Ok(ImportReport::default()); };
+        let columns: Vec<String> = split_csv_line(header).iter().map(|c| c.trim().to_lowercase()).collect();
+        let column = |name: &str| columns.iter().position(|c| c == name);
+        let (Some(name_col), Some(brand_col), Some(category_col)) = (column("name"), column("brand"), column("category")) else {
+            return Err(CatalogError::Parse { line: 1, reason: "header must contain name, brand and category".into() });
+        };
+        let description_col = column("description");
+
+        let records = lines
+            .map(|(i, line)| {
+                let fields = split_csv_line(line);
+                let parsed = if fields.len() != columns.len() {
+                    Err(format!("expected {} fields, found {}", columns.len(), fields.len()))
+                } else {
+                    Ok(Product {
+                        name: fields[name_col].clone(),
+                        brand: fields[brand_col].clone(),
+                        category: fields[category_col].clone(),
+                        description: description_col.map(|c| fields[c].clone()).filter(|d| !d.is_empty()),
+                        ..Default::default()
+                    })
+                };
+                (i + 1, parsed)
+            })
+            .collect();
+        self.import_records(records, mode)
+    }
+
+    fn import_records(
+        &mut self,
+        records: Vec<(usize, Result<Product, String>)>,
+        mode: ImportMode,
+    ) -> Result<ImportReport, CatalogError> {
+        let mut report = ImportReport::default();
+        let mut seen: HashSet<(String, String)> = self.products.values()
+            .map(|p| (self.normalize_key(&p.name), self.normalize_key(&p.brand)))
+            .collect();
+        let mut accepted = Vec::new();
+
+        for (line, record) in records {
+            report.processed += 1;
+            let product = match record.and_then(validate_product) {
+                Ok(p) => p,
+                Err(reason) if mode == ImportMode::Strict => return Err(CatalogError::Invalid { line, reason }),
+                Err(reason) => {
+                    report.rejected.push(RejectedRecord { line, reason });
+                    continue;
+                }
+            };
+            if !seen.insert((self.normalize_key(&product.name), self.normalize_key(&product.brand))) {
+                report.duplicates += 1;
+                continue;
+            }
+            accepted.push(product);
+        }
+
+        report.added = accepted.len();
+        for p in accepted {
+            self.add_product(p);
+        }
+        Ok(report)
+    }
+
+    pub fn snapshot(&self) -> CatalogSnapshot {
+        let mut products: Vec<Product> = self.products.values().cloned().collect();
+        products.sort_by_key(|p| p.id);
+        let mut edges: Vec<(usize, usize, Vec<ReasonCode>)> = self.rec_graph.edges.keys()
+            .map(|&(a, b)| (a, b, self.rec_graph.edge_reasons(a, b)))
+            .collect();
+        edges.sort_unstable();
+        let mut edge_weights: Vec<(usize, usize, f32)> = self.rec_graph.edges.iter()
+            .filter(|(_, meta)| meta.weight != 1.0)
+            .map(|(&(a, b), meta)| (a, b, meta.weight))
+            .collect();
+        edge_weights.sort_by_key(|&(a, b, _)| (a, b));
+        let mut pins: Vec<(String, Vec<usize>)> = self.pins.iter().map(|(k, ids)| (k.clone(), ids.clone())).collect();
+        pins.sort_unstable();
+        let mut popularity: Vec<(usize, usize)> = self.popularity.iter().map(|(&id, &n)| (id, n)).collect();
+        popularity.sort_unstable();
+
+        CatalogSnapshot { next_id: self.next_id, products, edges, edge_weights, pins, popularity }
+    }
+
+    /// Replaces all catalog state with `snapshot`, keeping the tokenizer
+    /// configuration and rebuilding the indexes (and the similarity matrix,
+    /// if one was precomputed).
+    pub fn restore(&mut self, snapshot: CatalogSnapshot) {
+        let config = self.hash_index.tokenizer.config.clone();
+        self.products.clear();
+        self.hash_index = HashIndex::new(Tokenizer::new(config), self.name_tree.backend);
+        self.rec_graph = RecGraph::new();
+        self.name_tree = NameIndex::new(self.name_tree.backend);
+        self.categories.clear();
+        let similarity_k = self.similarity.take().map(|m| m.top_k);
+
+        for p in snapshot.products {
+            self.hash_index.index_product(&p);
+            self.name_tree.insert(&p.name, p.id);
+            self.categories.entry(self.normalize_key(&p.category)).or_default().insert(p.id);
+            self.products.insert(p.id, p);
+        }
+        let max_id = self.products.keys().max().copied().unwrap_or(0);
+        self.next_id = snapshot.next_id.max(max_id + 1);
+        for (a, b, reasons) in snapshot.edges {
+            for reason in reasons {
+                self.rec_graph.add_edge_with_reason(a, b, reason);
+            }
+        }
+        for (a, b, weight) in snapshot.edge_weights {
+            self.rec_graph.add_weighted_edge(a, b, weight);
+        }
+        self.pins = snapshot.pins.into_iter().collect();
+        self.popularity = snapshot.popularity.into_iter().collect();
+        self.rebuild_trending();
+        self.invalidate_recommendations();
+        if let Some(top_k) = similarity_k {
+            self.precompute_similarity(top_k);
+        }
+    }
+
+    /// Renumbers products to `1..=n` in their current id order and returns
+    /// the old -> new mapping. Edges, pins, popularity and logged views are
+    /// rewritten; views of products no longer in the catalog are dropped.
+    /// A precomputed similarity matrix is rebuilt with the same `top_k`.
+    pub fn compact(&mut self) -> HashMap<usize, usize> {
+        let mut ids: Vec<usize> = self.products.keys().copied().collect();
+        ids.sort_unstable();
+        let mapping: HashMap<usize, usize> = ids.iter().enumerate().map(|(i, &old)| (old, i + 1)).collect();
+
+        let mut snapshot = self.snapshot();
+        for p in &mut snapshot.products {
+            p.id = mapping[&p.id];
+        }
+        snapshot.next_id = mapping.len() + 1;
+        snapshot.edges.retain_mut(|(a, b, _)| match (mapping.get(a), mapping.get(b)) {
+            (Some(&na), Some(&nb)) => {
+                (*a, *b) = edge_key(na, nb);
+                true
+            }
+            _ => false,
+        });
+        snapshot.edge_weights.retain_mut(|(a, b, _)| match (mapping.get(a), mapping.get(b)) {
+            (Some(&na), Some(&nb)) => {
+                (*a, *b) = edge_key(na, nb);
+                true
+            }
+            _ => false,
+        });
+        for (_, pinned) in &mut snapshot.pins {
+            *pinned = pinned.iter().filter_map(|id| mapping.get(id).copied()).collect();
+        }
+        snapshot.popularity = snapshot.popularity.iter()
+            .filter_map(|&(id, n)| mapping.get(&id).map(|&new| (new, n)))
+            .collect();
+
+        if let Some(log) = self.query_log.get_mut().unwrap().as_mut() {
+            log.entries.retain_mut(|entry| match entry.event {
+                LogEvent::View(id) => mapping.get(&id).map(|&new| entry.event = LogEvent::View(new)).is_some(),
+                LogEvent::Query => true,
+            });
+        }
+        self.restore(snapshot);
+        mapping
+    }
+
+    pub fn save_to<S: CatalogStore + ?Sized>(&self, store: &S) -> Result<(), CatalogError> {
+        store.save_snapshot(&self.snapshot())
+    }
+
+    pub fn load_from<S: CatalogStore + ?Sized>(&mut self, store: &S) -> Result<(), CatalogError> {
+        let snapshot = store.load_snapshot()?;
+        self.restore(snapshot);
+        Ok(())
+    }
+
+    pub fn get_product(&self, id: usize) -> Option<&Product> {
+        self.products.get(&id)
+    }
+
+    pub fn average_document_length(&self) -> f32 {
+        self.hash_index.avg_doc_len()
+    }
+
+    #[cfg(any(test, feature = "invariants"))]
+    pub fn check_invariants(&self) {
+        self.hash_index.check_invariants(&self.products);
+
+        let mut expected: HashMap<String, HashSet<usize>> = HashMap::new();
+        for p in self.products.values() {
+            expected.entry(self.normalize_key(&p.category)).or_default().insert(p.id);
+        }
+        assert_eq!(self.categories, expected, "category index drifted");
+    }
+
+    pub fn products_in_category(&self, category: &str) -> Vec<&Product> {
+        let mut products: Vec<&Product> = self.categories.get(&self.normalize_key(category))
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.products.get(id))
+            .collect();
+        products.sort_by_key(|p| p.id);
+        products
+    }
+
+    pub fn add_recommendation_edge(&mut self, a: usize, b: usize) {
+        self.rec_graph.add_edge(a, b);
+        self.refresh_trending(&[a, b]);
+        self.invalidate_seeds(&self.affected_seeds(&[a, b]));
+    }
+
+    pub fn add_recommendation_edge_with_reason(&mut self, a: usize, b: usize, reason: ReasonCode) {
+        self.rec_graph.add_edge_with_reason(a, b, reason);
+        self.refresh_trending(&[a, b]);
+        self.invalidate_seeds(&self.affected_seeds(&[a, b]));
+    }
+
+    pub fn add_weighted_recommendation_edge(&mut self, a: usize, b: usize, weight: f32) {
+        self.rec_graph.add_weighted_edge(a, b, weight);
+        self.refresh_trending(&[a, b]);
+        self.invalidate_seeds(&self.affected_seeds(&[a, b]));
+    }
+
+    pub fn add_recommendation_edges(&mut self, edges: &[(usize, usize)]) {
+        self.rec_graph.add_edges(edges);
+        let touched: Vec<usize> = edges.iter()
+            .flat_map(|&(a, b)| [a, b])
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        self.refresh_trending(&touched);
+        self.invalidate_seeds(&self.affected_seeds(&touched));
+    }
+
+    pub fn remove_recommendation_edge(&mut self, a: usize, b: usize) -> bool {
+        let removed = self.rec_graph.remove_edge(a, b);
+        if removed {
+            self.refresh_trending(&[a, b]);
+            self.invalidate_seeds(&self.affected_seeds(&[a, b]));
+        }
+        removed
+    }
+
+    pub fn graph(&self) -> &RecGraph {
+        &self.rec_graph
+    }
+
+    pub fn increment_popularity(&mut self, id: usize, amount: usize) {
+        if !self.products.contains_key(&id) {
+            return;
+        }
+        *self.popularity.entry(id).or_default() += amount;
+        self.refresh_trending(&[id]);
+    }
+
+    pub fn popularity(&self, id: usize) -> usize {
+        self.popularity.get(&id).copied().unwrap_or(0)
+    }
+
+    fn trending_score(&self, id: usize) -> usize {
+        self.rec_graph.degree(id) + self.popularity(id)
+    }
+
+    fn refresh_trending(&mut self, ids: &[usize]) {
+        let mut needs_rebuild = false;
+        // Edges may name ids that are not products; they must not take shelf slots.
+        for &id in ids.iter().filter(|&&id| self.products.contains_key(&id)) {
+            let score = self.trending_score(id);
+            needs_rebuild |= self.trending.update(id, score);
+        }
+        if needs_rebuild {
+            self.rebuild_trending();
+        }
+    }
+
+    fn rebuild_trending(&mut self) {
+        let mut candidates: HashSet<usize> = self.rec_graph.adj.keys().copied().collect();
+        candidates.extend(self.popularity.keys().copied());
+        candidates.retain(|id| self.products.contains_key(id));
+        let scores = candidates.into_iter().map(|id| (id, self.trending_score(id))).collect();
+        self.trending.rebuild(scores);
+    }
+
+    pub fn set_trending_size(&mut self, n: usize) {
+        self.trending = TopN::new(n);
+        self.rebuild_trending();
+    }
+
+    pub fn trending(&self) -> Vec<&Product> {
+        self.trending.ids()
+            .filter_map(|id| self.products.get(&id))
+            .collect()
+    }
+
+    fn invalidate_recommendations(&mut self) {
+        self.rec_cache.get_mut().unwrap().clear();
+    }
+
+    /// Cached rankings for a seed depend on its neighbors' degrees,
+    /// adjacency and flags, so a change at `nodes` can only affect seeds in
+    /// `nodes` or adjacent to them.
+    fn affected_seeds(&self, nodes: &[usize]) -> HashSet<usize> {
+        nodes.iter()
+            .flat_map(|&n| self.rec_graph.adj.get(&n).into_iter().flatten().copied().chain([n]))
+            .collect()
+    }
+
+    fn invalidate_seeds(&mut self, seeds: &HashSet<usize>) {
+        self.rec_cache.get_mut().unwrap().retain(|(seed, _, _), _| !seeds.contains(seed));
+    }
+
+    pub fn recommendation_cache_len(&self) -> usize {
+        self.rec_cache.lock().unwrap().len()
+    }
+
+    pub fn enable_query_log(&mut self, capacity: usize) {
+        self.enable_query_log_with_clock(capacity, || {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+        });
+    }
+
+    pub fn enable_query_log_with_clock<C>(&mut self, capacity: usize, clock: C)
+    where
+        C: Fn() -> u64 + Send + Sync + 'static,
+    {
+        *self.query_log.get_mut().unwrap() = Some(QueryLog::new(capacity, Box::new(clock)));
+    }
+
+    pub fn disable_query_log(&mut self) {
+        *self.query_log.get_mut().unwrap() = None;
+    }
+
+    pub fn export_query_log(&self) -> Vec<QueryLogEntry> {
+        self.query_log.lock().unwrap()
+            .as_ref()
+            .map(|log| log.entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Tags subsequently logged queries with `session` until it is changed again.
+    pub fn set_query_session(&self, session: Option<&str>) {
+        if let Some(log) = self.query_log.lock().unwrap().as_mut() {
+            log.session = session.map(String::from);
+        }
+    }
+
+    pub fn related_queries(&self, query: &str, limit: usize) -> Vec<(String, usize)> {
+        let key = self.query_key(query);
+        let mut sessions: HashMap<String, HashSet<String>> = HashMap::new();
+        for entry in self.export_query_log() {
+            if let (Some(session), LogEvent::Query) = (entry.session, entry.event) {
+                sessions.entry(session).or_default().insert(entry.query);
+            }
+        }
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for queries in sessions.values().filter(|q| q.contains(&key)) {
+            for q in queries.iter().filter(|q| **q != key && !q.is_empty()) {
+                *counts.entry(q.clone()).or_default() += 1;
+            }
+        }
+
+        let mut related: Vec<(String, usize)> = counts.into_iter().collect();
+        related.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        related.truncate(limit);
+        related
+    }
+
+    pub fn record_view(&self, product_id: usize) {
+        if let Some(log) = self.query_log.lock().unwrap().as_mut() {
+            log.record(String::new(), 0, LogEvent::View(product_id));
+        }
+    }
+
+    pub fn followup_searches(&self, product_id: usize, limit: usize) -> Vec<(String, usize)> {
+        let mut viewed_in: HashSet<String> = HashSet::new();
+        let mut pairs: HashSet<(String, String)> = HashSet::new();
+        for entry in self.export_query_log() {
+            let Some(session) = entry.session else { continue; };
+            match entry.event {
+                LogEvent::View(id) if id == product_id => {
+                    viewed_in.insert(session);
+                }
+                LogEvent::Query if viewed_in.contains(&session) && !entry.query.is_empty() => {
+                    pairs.insert((session, entry.query));
+                }
+                _ => {}
+            }
+        }
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (_, query) in pairs {
+            *counts.entry(query).or_default() += 1;
+        }
+        let mut followups: Vec<(String, usize)> = counts.into_iter().collect();
+        followups.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        followups.truncate(limit);
+        followups
+    }
+
+    fn log_query(&self, query: &str, result_count: usize) {
+        if let Some(log) = self.query_log.lock().unwrap().as_mut() {
+            log.record(self.query_key(query), result_count, LogEvent::Query);
+        }
+    }
+
+    /// Normalized log key that keeps negated words apart from plain ones:
+    /// "Notebook -XPS" becomes "notebook -xps".
+    fn query_key(&self, query: &str) -> String {
+        let (positive, negated) = split_negated(query);
+        let mut key = self.tokenize(&positive.join(" "));
+        key.extend(self.tokenize(&negated.join(" ")).into_iter().map(|t| format!("-{t}")));
+        key.join(" ")
+    }
+
+    pub fn search_exact_name(&self, name: &str) -> Vec<&Product> {
+        let key = name.to_lowercase();
+        self.products.values()
+            .filter(|p| p.name.to_lowercase() == key)
+            .collect()
+    }
+
+    /// Splits off words with a leading `-` and returns the remaining query
+    /// tokens plus every product matching any negated word. A query made only
+    /// of negated words has no positive tokens and therefore matches nothing.
+    fn parse_negations(&self, query: &str) -> (Vec<String>, HashSet<usize>) {
+        let (positive, negated) = split_negated(query);
+        let excluded = self.tokenize(&negated.join(" ")).iter()
+            .filter_map(|t| self.hash_index.index.get(t))
+            .flatten()
+            .copied()
+            .collect();
+        (self.tokenize(&positive.join(" ")), excluded)
+    }
+
+    fn token_matches(&self, query: &str) -> Vec<&Product> {
+        let (tokens, excluded) = self.parse_negations(query);
+        let ids = self.hash_index.search_tokens_and(&tokens);
+        ids.iter()
+            .filter(|id| !excluded.contains(id))
+            .filter_map(|id| self.products.get(id))
+            .collect()
+    }
+
+    pub fn search_tokens(&self, query: &str) -> Vec<&Product> {
+        #[cfg(feature = "tracing")]
+        let (_span, start) = (tracing::info_span!("catalog.search_tokens", query).entered(), Instant::now());
+        let results = self.token_matches(query);
+        self.log_query(query, results.len());
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            tokens = self.parse_negations(query).0.len(),
+            results = results.len(),
+            elapsed_us = start.elapsed().as_micros() as u64,
+            "search finished"
+        );
+        results
+    }
+
+    pub fn search_tokens_lang(&self, query: &str, lang: Option<&str>) -> Vec<&Product> {
+        let tokenizer = &self.hash_index.tokenizer;
+        let tokens = tokenizer.tokenize_lang(query, lang);
+        let wanted = lang.map(|l| tokenizer.language(Some(l)));
+        let results: Vec<&Product> = self.hash_index.search_tokens_and(&tokens).iter()
+            .filter_map(|id| self.products.get(id))
+            .filter(|p| wanted.as_ref().is_none_or(|l| *l == tokenizer.language(p.lang.as_deref())))
+            .collect();
+        self.log_query(query, results.len());
+        results
+    }
+
+    pub fn search_in_fields(&self, query: &str, fields: &[Field]) -> Vec<&Product> {
+        let (tokens, excluded) = self.parse_negations(query);
+        let ids = self.hash_index.search_fields_and(&tokens, fields);
+        let results: Vec<&Product> = ids.iter()
+            .filter(|id| !excluded.contains(id))
+            .filter_map(|id| self.products.get(id))
+            .collect();
+        self.log_query(query, results.len());
+        results
+    }
+
+    pub fn search_excluding_categories(&self, query: &str, exclude: &[String]) -> Vec<&Product> {
+        let excluded: HashSet<String> = exclude.iter().map(|c| self.normalize_key(c)).collect();
+        let results: Vec<&Product> = self.token_matches(query)
+            .into_iter()
+            .filter(|p| !excluded.contains(&self.normalize_key(&p.category)))
+            .collect();
+        self.log_query(query, results.len());
+        results
+    }
+
+    /// Counts the products matching `query` in each `[min, max)` price bucket.
+    /// Overlapping buckets are allowed and a product is counted in every bucket
+    /// containing its price; products without a price fall outside all buckets.
+    pub fn price_histogram(&self, query: &str, buckets: &[(f64, f64)]) -> Vec<usize> {
+        let prices: Vec<f64> = self.token_matches(query).iter().filter_map(|p| p.price).collect();
+        buckets.iter()
+            .map(|&(min, max)| prices.iter().filter(|&&price| price >= min && price < max).count())
+            .collect()
+    }
+
+    /// When enabled, scored searches keep only the best-ranked (then lowest-id)
+    /// product among those sharing a normalized name. Disabled by default so
+    /// admin tooling sees every duplicate.
+    pub fn set_dedupe_by_name(&mut self, enabled: bool) {
+        self.dedupe_by_name = enabled;
+    }
+
+    fn dedupe_names<T>(&self, items: Vec<T>, product: impl Fn(&T) -> &Product) -> Vec<T> {
+        if !self.dedupe_by_name {
+            return items;
+        }
+        let mut seen = HashSet::new();
+        items.into_iter()
+            .filter(|item| seen.insert(self.normalize_key(&product(item).name)))
+            .collect()
+    }
+
+    pub fn set_empty_query_policy(&mut self, policy: EmptyQueryPolicy) {
+        self.empty_query_policy = policy;
+    }
+
+    /// Searches with the catalog's `EmptyQueryPolicy`. Under `Error`, an
+    /// empty query yields an empty result; use `search_with_policy(.., None)`
+    /// to get the error itself.
+    pub fn search(&self, query: &str, limit: usize) -> SearchResult<'_> {
+        self.search_with_policy(query, limit, None).unwrap_or_else(|_| SearchResult {
+            products: Vec::new(),
+            total: 0,
+            elapsed: Duration::ZERO,
+            truncated: false,
+        })
+    }
+
+    /// Like `search`, but reports `QueryError::Empty` under the `Error`
+    /// policy. `None` uses the catalog's policy.
+    pub fn search_with_policy(
+        &self,
+        query: &str,
+        limit: usize,
+        policy: Option<EmptyQueryPolicy>,
+    ) -> Result<SearchResult<'_>, QueryError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("catalog.search", query, limit).entered();
+        let start = Instant::now();
+        let products = if self.tokenize(query).is_empty() {
+            match policy.unwrap_or(self.empty_query_policy) {
+                EmptyQueryPolicy::Empty => Vec::new(),
+                EmptyQueryPolicy::All => {
+                    let mut all: Vec<&Product> = self.products.values().collect();
+                    all.sort_by_key(|p| p.id);
+                    all
+                }
+                EmptyQueryPolicy::Error => return Err(QueryError::Empty),
+            }
+        } else {
+            self.negated_ranked_matches(query).1.into_iter().map(|(p, _)| p).collect()
+        };
+        let mut products = self.dedupe_names(products, |p| p);
+        let total = products.len();
+        products.truncate(limit);
+        self.log_query(query, total);
+        let elapsed = start.elapsed();
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            tokens = self.tokenize(query).len(),
+            results = total,
+            elapsed_us = elapsed.as_micros() as u64,
+            "search finished"
+        );
+        Ok(SearchResult {
+            truncated: products.len() < total,
+            products,
+            total,
+            elapsed,
+        })
+    }
+
+    pub fn search_fuzzy(&self, query: &str, max_distance: usize) -> Vec<&Product> {
+        self.search_fuzzy_with(query, FuzzyDistance::Fixed(max_distance))
+    }
+
+    pub fn search_fuzzy_with(&self, query: &str, distance: FuzzyDistance) -> Vec<&Product> {
+        let tokens = self.tokenize(query);
+        let mut ids = self.hash_index.search_fuzzy_and(&tokens, distance);
+        ids.sort_unstable();
+        let results: Vec<&Product> = ids.iter()
+            .filter_map(|id| self.products.get(id))
+            .collect();
+        self.log_query(query, results.len());
+        results
+    }
+
+    pub fn search_tiered(&self, query: &str, limit: usize) -> Vec<(&Product, SearchTier)> {
+        let (tokens, excluded) = self.parse_negations(query);
+        let tiers = [
+            (SearchTier::Exact, self.hash_index.search_tokens_and(&tokens)),
+            (SearchTier::Prefix, self.hash_index.search_prefix_and(&tokens)),
+            (SearchTier::Fuzzy, self.hash_index.search_fuzzy_and(&tokens, FuzzyDistance::Fixed(TIERED_FUZZY_DISTANCE))),
+        ];
+
+        let mut seen = HashSet::new();
+        let mut out = Vec::new();
+        'tiers: for (tier, mut ids) in tiers {
+            ids.sort_unstable();
+            for id in ids {
+                if out.len() >= limit {
+                    break 'tiers;
+                }
+                if excluded.contains(&id) || !seen.insert(id) {
+                    continue;
+                }
+                if let Some(p) = self.products.get(&id) {
+                    out.push((p, tier));
+                }
+            }
+        }
+        self.log_query(query, out.len());
+        out
+    }
+
+    pub fn search_smart(&self, query: &str, limit: usize) -> (Vec<&Product>, Option<SearchTier>) {
+        let (excluded, matches) = self.negated_ranked_matches(query);
+        let exact: Vec<&Product> = self.dedupe_names(matches, |(p, _)| p).into_iter().map(|(p, _)| p).take(limit).collect();
+        let (results, tier) = if !exact.is_empty() {
+            (exact, Some(SearchTier::Exact))
+        } else {
+            let positive = split_negated(query).0.join(" ");
+            let candidates = if positive.is_empty() { Vec::new() } else { self.name_tree.search_prefix(&positive, usize::MAX) };
+            let mut prefix: Vec<&Product> = candidates.iter()
+                .filter(|id| !excluded.contains(id))
+                .filter_map(|id| self.products.get(id))
+                .collect();
+            prefix = self.dedupe_names(prefix, |p| p);
+            prefix.truncate(limit);
+            let tier = (!prefix.is_empty()).then_some(SearchTier::Prefix);
+            (prefix, tier)
+        };
+        self.log_query(query, results.len());
+        (results, tier)
+    }
+
+    pub fn search_relaxed(&self, query: &str, limit: usize) -> (Vec<&Product>, Relaxation) {
+        let (tokens, excluded) = self.parse_negations(query);
+        let (mut ids, relaxation) = self.relax(&tokens, &excluded);
+        ids.sort_unstable();
+        let results: Vec<&Product> = ids.iter()
+            .filter_map(|id| self.products.get(id))
+            .take(limit)
+            .collect();
+        self.log_query(query, results.len());
+        (results, relaxation)
+    }
+
+    fn relax(&self, tokens: &[String], excluded: &HashSet<usize>) -> (Vec<usize>, Relaxation) {
+        let keep = |ids: Vec<usize>| -> Vec<usize> { ids.into_iter().filter(|id| !excluded.contains(id)).collect() };
+        if tokens.is_empty() {
+            return (Vec::new(), Relaxation::Exhausted);
+        }
+        let strict = keep(self.hash_index.search_tokens_and(tokens));
+        if !strict.is_empty() {
+            return (strict, Relaxation::None);
+        }
+
+        // Drop one token at a time, rarest first (unknown tokens go first), so
+        // long queries cost a linear number of AND searches.
+        let mut order: Vec<usize> = (0..tokens.len()).collect();
+        order.sort_by_key(|&i| self.hash_index.index.get(&tokens[i]).map_or(0, |ids| ids.len()));
+        for dropped in 1..tokens.len() {
+            let kept: Vec<String> = order[dropped..].iter().map(|&i| tokens[i].clone()).collect();
+            let found = keep(self.hash_index.search_tokens_and(&kept));
+            if !found.is_empty() {
+                return (found, Relaxation::DroppedTokens(dropped));
+            }
+        }
+
+        let any_token = |search: &dyn Fn(&[String]) -> Vec<usize>| -> Vec<usize> {
+            tokens.iter()
+                .flat_map(|t| search(std::slice::from_ref(t)))
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect()
+        };
+        let prefix = keep(any_token(&|t| self.hash_index.search_prefix_and(t)));
+        if !prefix.is_empty() {
+            return (prefix, Relaxation::Prefix);
+        }
+        let fuzzy = keep(any_token(&|t| self.hash_index.search_fuzzy_and(t, FuzzyDistance::Fixed(TIERED_FUZZY_DISTANCE))));
+        if !fuzzy.is_empty() {
+            return (fuzzy, Relaxation::Fuzzy);
+        }
+        (Vec::new(), Relaxation::Exhausted)
+    }
+
+    pub fn pin_product(&mut self, query_key: &str, product_id: usize) {
+        let pinned = self.pins.entry(self.normalize_key(query_key)).or_default();
+        if !pinned.contains(&product_id) {
+            pinned.push(product_id);
+        }
+    }
+
+    pub fn search_tokens_scored(&self, query: &str, limit: usize) -> Vec<(&Product, f32)> {
+        let (tokens, excluded) = self.parse_negations(query);
+        let mut scored: Vec<(&Product, f32)> = self.hash_index.search_tokens_and(&tokens).into_iter()
+            .filter(|id| !excluded.contains(id))
+            .filter_map(|id| self.products.get(&id))
+            .map(|p| (p, self.hash_index.and_score(p.id, &tokens)))
+            .collect();
+        self.sort_ranked(&mut scored);
+        let mut scored = self.dedupe_names(scored, |(p, _)| p);
+        scored.truncate(limit);
+        self.log_query(query, scored.len());
+        scored
+    }
+
+    fn ranked_matches(&self, tokens: &[String]) -> Vec<(&Product, f32)> {
+        let pinned: Vec<(&Product, f32)> = self.pins.get(&tokens.join(" "))
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.products.get(id))
+            .map(|p| (p, self.hash_index.bm25(p, tokens) * self.promotion_factor(p)))
+            .collect();
+
+        let mut organic: Vec<(&Product, f32)> = self.hash_index.search_tokens_and(tokens).iter()
+            .filter(|id| !pinned.iter().any(|(p, _)| p.id == **id))
+            .filter_map(|id| self.products.get(id))
+            .map(|p| (p, self.hash_index.bm25(p, tokens)))
+            .collect();
+        self.sort_ranked(&mut organic);
+
+        pinned.into_iter().chain(organic).collect()
+    }
+
+    fn negated_ranked_matches(&self, query: &str) -> (HashSet<usize>, Vec<(&Product, f32)>) {
+        let (tokens, excluded) = self.parse_negations(query);
+        let mut matches = self.ranked_matches(&tokens);
+        matches.retain(|(p, _)| !excluded.contains(&p.id));
+        (excluded, matches)
+    }
+
+    pub fn explain_score(&self, product_id: usize, query: &str) -> ScoreBreakdown {
+        let Some(p) = self.products.get(&product_id) else { return ScoreBreakdown::default(); };
+        let terms = self.hash_index.bm25_terms(p, &self.parse_negations(query).0);
+        let base: f32 = terms.iter().map(|t| t.score).sum();
+        let total = base * self.promotion_factor(p);
+        ScoreBreakdown { terms, promotion_boost: total - base, total }
+    }
+
+    pub fn search_ranked(&self, query: &str, limit: usize) -> Vec<(&Product, f32)> {
+        #[cfg(feature = "tracing")]
+        let (_span, start) = (tracing::info_span!("catalog.search_ranked", query, limit).entered(), Instant::now());
+        let (tokens, excluded) = self.parse_negations(query);
+        let mut matches = self.ranked_matches(&tokens);
+        matches.retain(|(p, _)| !excluded.contains(&p.id));
+        let mut results = self.dedupe_names(matches, |(p, _)| p);
+        results.truncate(limit);
+        self.log_query(query, results.len());
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            tokens = tokens.len(),
+            results = results.len(),
+            elapsed_us = start.elapsed().as_micros() as u64,
+            "search finished"
+        );
+        results
+    }
+
+    /// Groups ranked matches by the value of `by`. Larger clusters come first
+    /// (ties keep the order of their best match) and products keep their
+    /// relevance order; missing or blank values go to a "(none)" cluster.
+    pub fn search_clustered(&self, query: &str, by: Field) -> Vec<(String, Vec<&Product>)> {
+        let (_, matches) = self.negated_ranked_matches(query);
+        let mut clusters: Vec<(String, Vec<&Product>)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+
+        for (p, _) in self.dedupe_names(matches, |(p, _)| p) {
+            let key = p.field(by).map(str::trim).filter(|v| !v.is_empty()).unwrap_or("(none)").to_string();
+            match positions.get(&key) {
+                Some(&pos) => clusters[pos].1.push(p),
+                None => {
+                    positions.insert(key.clone(), clusters.len());
+                    clusters.push((key, vec![p]));
+                }
+            }
+        }
+        clusters.sort_by_key(|(_, ps)| std::cmp::Reverse(ps.len()));
+        self.log_query(query, clusters.iter().map(|(_, ps)| ps.len()).sum());
+        clusters
+    }
+
+    pub fn search_collapsed(&self, query: &str, limit: usize) -> Vec<(&Product, usize)> {
+        let (_, matches) = self.negated_ranked_matches(query);
+        let mut out: Vec<(&Product, usize)> = Vec::new();
+        let mut groups: HashMap<&str, usize> = HashMap::new();
+
+        for (p, _) in matches {
+            match p.group_id.as_deref() {
+                Some(group) => match groups.get(group) {
+                    Some(&pos) => out[pos].1 += 1,
+                    None => {
+                        groups.insert(group, out.len());
+                        out.push((p, 1));
+                    }
+                },
+                None => out.push((p, 1)),
+            }
+        }
+        out.truncate(limit);
+        self.log_query(query, out.len());
+        out
+    }
+
+    pub fn search_by_authority(
+        &self,
+        query: &str,
+        limit: usize,
+        relevance_weight: f32,
+        authority_weight: f32,
+    ) -> Vec<(&Product, f32)> {
+        let (tokens, excluded) = self.parse_negations(query);
+        let matches: Vec<(&Product, f32)> = self.hash_index.search_tokens_and(&tokens).iter()
+            .filter(|id| !excluded.contains(id))
+            .filter_map(|id| self.products.get(id))
+            .map(|p| (p, self.hash_index.bm25(p, &tokens)))
+            .collect();
+        let max_relevance = matches.iter().map(|(_, score)| *score).fold(0.0, f32::max);
+
+        let mut blended: Vec<(&Product, f32)> = matches.into_iter()
+            .map(|(p, score)| {
+                let relevance = if max_relevance > 0.0 { score / max_relevance } else { 0.0 };
+                let authority = self.rec_graph.degree_centrality(p.id);
+                (p, relevance_weight * relevance + authority_weight * authority)
+            })
+            .collect();
+        self.sort_ranked(&mut blended);
+        let mut blended = self.dedupe_names(blended, |(p, _)| p);
+        blended.truncate(limit);
+        self.log_query(query, blended.len());
+        blended
+    }
+
+    /// While enabled, scores of products flagged `on_promotion` are multiplied
+    /// by the promotion multiplier in search and recommendation rankings.
+    /// Only ordering changes; the set of matches is untouched.
+    pub fn set_promote_sales(&mut self, enabled: bool) {
+        self.promote_sales = enabled;
+        self.invalidate_recommendations();
+    }
+
+    pub fn set_promotion_multiplier(&mut self, multiplier: f32) {
+        self.promotion_multiplier = multiplier;
+        self.invalidate_recommendations();
+    }
+
+    fn promotion_factor(&self, p: &Product) -> f32 {
+        if self.promote_sales && p.on_promotion { self.promotion_multiplier } else { 1.0 }
+    }
+
+    fn sort_ranked(&self, scored: &mut [(&Product, f32)]) {
+        for (p, score) in scored.iter_mut() {
+            *score *= self.promotion_factor(p);
+        }
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.id.cmp(&b.0.id)));
+    }
+
+    fn ranked_recommendations(&self, product_id: usize, mode: RecommendMode) -> Vec<usize> {
+        self.promote_scored(self.rec_graph.scored_neighbors(product_id, mode))
+    }
+
+    fn promote_scored(&self, mut scored: Vec<(usize, f32)>) -> Vec<usize> {
+        if self.promote_sales {
+            for (id, score) in scored.iter_mut() {
+                *score *= self.products.get(id).map_or(1.0, |p| self.promotion_factor(p));
+            }
+            scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        }
+        scored.into_iter().map(|(id, _)| id).collect()
+    }
+
+    pub fn set_rating_config(&mut self, config: RatingConfig) {
+        self.rating_config = config;
+    }
+
+    pub fn bayesian_rating(&self, product_id: usize) -> Option<f32> {
+        let p = self.products.get(&product_id)?;
+        let RatingConfig { prior_mean, prior_weight, .. } = self.rating_config;
+        let count = p.rating_count as f32;
+        let total = prior_weight + count;
+        Some(if total > 0.0 { (prior_weight * prior_mean + p.rating * count) / total } else { prior_mean })
+    }
+
+    pub fn search_rated(&self, query: &str, limit: usize) -> Vec<(&Product, f32)> {
+        let (tokens, excluded) = self.parse_negations(query);
+        let matches: Vec<(&Product, f32, f32)> = self.hash_index.search_tokens_and(&tokens).iter()
+            .filter(|id| !excluded.contains(id))
+            .filter_map(|id| self.products.get(id))
+            .map(|p| (p, self.hash_index.bm25(p, &tokens), self.bayesian_rating(p.id).unwrap_or(0.0)))
+            .collect();
+        let max_relevance = matches.iter().map(|m| m.1).fold(0.0, f32::max);
+        let max_rating = matches.iter().map(|m| m.2).fold(0.0, f32::max);
+        let normalize = |value: f32, max: f32| if max > 0.0 { value / max } else { 0.0 };
+
+        let mut blended: Vec<(&Product, f32)> = matches.into_iter()
+            .map(|(p, relevance, rating)| {
+                let score = self.rating_config.relevance_weight * normalize(relevance, max_relevance)
+                    + self.rating_config.rating_weight * normalize(rating, max_rating);
+                (p, score)
+            })
+            .collect();
+        self.sort_ranked(&mut blended);
+        let mut blended = self.dedupe_names(blended, |(p, _)| p);
+        blended.truncate(limit);
+        self.log_query(query, blended.len());
+        blended
+    }
+
+    pub fn search_or_scored(&self, query: &str, limit: usize) -> Vec<(&Product, f32)> {
+        let (tokens, excluded) = self.parse_negations(query);
+        let mut scored: Vec<(&Product, f32)> = self.hash_index.search_tokens_or_scored(&tokens)
+            .into_iter()
+            .filter(|(id, _)| !excluded.contains(id))
+            .filter_map(|(id, score)| self.products.get(&id).map(|p| (p, score)))
+            .collect();
+        self.sort_ranked(&mut scored);
+        let mut scored = self.dedupe_names(scored, |(p, _)| p);
+        scored.truncate(limit);
+        self.log_query(query, scored.len());
+        scored
+    }
+
+    pub fn parse_query(&self, query: &str) -> Result<QueryNode, QueryError> {
+        QueryParser { tokenizer: &self.hash_index.tokenizer, tokens: lex_query(query), pos: 0 }.parse()
+    }
+
+    /// Boolean search with `AND`/`OR`/`NOT`, parentheses and `term^boost`.
+    /// Adjacent terms are implicitly ANDed; a term's score is multiplied by
+    /// its boost (1.0 when omitted).
+    pub fn search_boolean(&self, query: &str, limit: usize) -> Result<Vec<(&Product, f32)>, QueryError> {
+        let node = self.parse_query(query)?;
+        let mut scored: Vec<(&Product, f32)> = self.evaluate(&node)
+            .into_iter()
+            .filter_map(|(id, score)| self.products.get(&id).map(|p| (p, score)))
+            .collect();
+        self.sort_ranked(&mut scored);
+        let mut scored = self.dedupe_names(scored, |(p, _)| p);
+        scored.truncate(limit);
+        self.log_query(query, scored.len());
+        Ok(scored)
+    }
+
+    fn evaluate(&self, node: &QueryNode) -> HashMap<usize, f32> {
+        match node {
+            QueryNode::Term(t) => self.hash_index.index.get(t)
+                .into_iter()
+                .flatten()
+                .map(|&id| (id, self.hash_index.and_score(id, std::slice::from_ref(t))))
+                .collect(),
+            QueryNode::Boost(inner, boost) => self.evaluate(inner)
+                .into_iter()
+                .map(|(id, score)| (id, score * boost))
+                .collect(),
+            QueryNode::Not(inner) => {
+                let excluded = self.evaluate(inner);
+                self.products.keys()
+                    .filter(|id| !excluded.contains_key(*id))
+                    .map(|&id| (id, 0.0))
+                    .collect()
+            }
+            QueryNode::Or(branches) => {
+                let mut scores: HashMap<usize, f32> = HashMap::new();
+                for branch in branches {
+                    for (id, score) in self.evaluate(branch) {
+                        *scores.entry(id).or_default() += score;
+                    }
+                }
+                scores
+            }
+            QueryNode::And(parts) => {
+                let mut result: Option<HashMap<usize, f32>> = None;
+                for part in parts {
+                    let scores = self.evaluate(part);
+                    result = Some(match result {
+                        None => scores,
+                        Some(acc) => acc.into_iter()
+                            .filter_map(|(id, score)| scores.get(&id).map(|s| (id, score + s)))
+                            .collect(),
+                    });
+                }
+                result.unwrap_or_default()
+            }
+        }
+    }
+
+    pub fn search_prefix_ordered(&self, prefix: &str, limit: usize) -> Vec<&Product> {
+        let ids = self.name_tree.search_prefix(prefix, limit);
+        ids.iter()
+            .filter_map(|id| self.products.get(id))
+            .collect()
+    }
+
+    /// Products whose lowercased name falls in `[from, to)`, in name order.
+    pub fn search_name_range(&self, from: &str, to: &str, limit: usize) -> Vec<&Product> {
+        let ids = self.name_tree.search_range(from, to, limit);
+        ids.iter()
+            .filter_map(|id| self.products.get(id))
+            .collect()
+    }
+
+    pub fn recommend_for(&self, product_id: usize, limit: usize) -> Vec<&Product> {
+        self.recommend_with(product_id, limit, RecommendMode::Degree)
+    }
+
+    /// Like `recommend_for`, but explains an empty result.
+    pub fn recommend_diagnosed(&self, product_id: usize, limit: usize) -> (Vec<&Product>, Option<RecommendDiagnostic>) {
+        let results = self.recommend_for(product_id, limit);
+        if !results.is_empty() {
+            return (results, None);
+        }
+        let diagnostic = if !self.products.contains_key(&product_id) {
+            RecommendDiagnostic::SeedMissing
+        } else if self.rec_graph.edges.is_empty() {
+            RecommendDiagnostic::GraphEmpty
+        } else if self.rec_graph.degree(product_id) == 0 {
+            RecommendDiagnostic::SeedIsolated
+        } else {
+            RecommendDiagnostic::NoCandidates
+        };
+        (results, Some(diagnostic))
+    }
+
+    pub fn recommend_with(&self, product_id: usize, limit: usize, mode: RecommendMode) -> Vec<&Product> {
+        #[cfg(feature = "tracing")]
+        let (_span, start) = (
+            tracing::info_span!("catalog.recommend", product_id, limit, mode = ?mode).entered(),
+            Instant::now(),
+        );
+        let mut cache = self.rec_cache.lock().unwrap();
+        let rec_ids = cache.entry((product_id, limit, mode))
+            .or_insert_with(|| {
+                self.filter_recommendable(self.ranked_recommendations(product_id, mode), limit)
+            });
+        let results: Vec<&Product> = rec_ids.iter()
+            .filter_map(|id| self.products.get(id))
+            .collect();
+        #[cfg(feature = "tracing")]
+        tracing::info!(results = results.len(), elapsed_us = start.elapsed().as_micros() as u64, "recommend finished");
+        results
+    }
+
+    pub fn recommend_by_reason(&self, product_id: usize, limit: usize, reasons: &[ReasonCode]) -> Vec<&Product> {
+        let scored = self.rec_graph.scored_neighbors_by_reason(product_id, reasons);
+        self.filter_recommendable(self.promote_scored(scored), limit)
+            .iter()
+            .filter_map(|id| self.products.get(id))
+            .collect()
+    }
+
+    /// Closer hops always come first; promotion only reorders products
+    /// within the same hop.
+    pub fn recommend_multi_hop(&self, product_id: usize, max_hops: usize, limit: usize) -> Vec<&Product> {
+        let mut ranked = self.rec_graph.multi_hop_distances(product_id, max_hops);
+        if self.promote_sales {
+            let score = |id: usize| {
+                self.rec_graph.degree(id) as f32 * self.products.get(&id).map_or(1.0, |p| self.promotion_factor(p))
+            };
+            ranked.sort_by(|a, b| a.1.cmp(&b.1).then(score(b.0).total_cmp(&score(a.0))).then(a.0.cmp(&b.0)));
+        }
+        self.filter_recommendable(ranked.into_iter().map(|(id, _)| id).collect(), limit)
+            .iter()
+            .filter_map(|id| self.products.get(id))
+            .collect()
+    }
+
+    /// Pages through the degree ranking after dropping `exclude`, so every
+    /// page is full while candidates remain. `offset` counts positions in
+    /// that filtered ranking: if products already shown are added to
+    /// `exclude` between requests, lower `offset` by the same amount (or
+    /// restart at 0 when everything shown is excluded) to avoid skipping
+    /// items. With an unchanged `exclude`, consecutive pages never overlap.
+    pub fn recommend_page_excluding(
+        &self,
+        seed: usize,
+        offset: usize,
+        limit: usize,
+        exclude: &HashSet<usize>,
+    ) -> Vec<&Product> {
+        self.filter_recommendable(self.ranked_recommendations(seed, RecommendMode::Degree), usize::MAX)
+            .into_iter()
+            .filter(|id| !exclude.contains(id))
+            .skip(offset)
+            .take(limit)
+            .filter_map(|id| self.products.get(&id))
+            .collect()
+    }
+
+    /// Neighbors outside the seed's category ("goes well with this"). Falls
+    /// back to same-category neighbors when there are none.
+    pub fn recommend_complementary(&self, product_id: usize, limit: usize) -> Vec<&Product> {
+        self.recommend_by_category(product_id, limit, false)
+    }
+
+    /// Neighbors sharing the seed's category ("more like this"). Falls back
+    /// to other-category neighbors when there are none.
+    pub fn recommend_similar(&self, product_id: usize, limit: usize) -> Vec<&Product> {
+        self.recommend_by_category(product_id, limit, true)
+    }
+
+    fn recommend_by_category(&self, product_id: usize, limit: usize, same_category: bool) -> Vec<&Product> {
+        let Some(seed) = self.products.get(&product_id) else { return Vec::new(); };
+        let category = self.normalize_key(&seed.category);
+        let ranked = self.filter_recommendable(self.ranked_recommendations(product_id, RecommendMode::Degree), usize::MAX);
+        let (preferred, fallback): (Vec<usize>, Vec<usize>) = ranked.into_iter()
+            .partition(|id| (self.normalize_key(&self.products[id].category) == category) == same_category);
+
+        let ids = if preferred.is_empty() { fallback } else { preferred };
+        ids.iter()
+            .take(limit)
+            .filter_map(|id| self.products.get(id))
+            .collect()
+    }
+
+    /// Recommendable products ranked by Jaccard overlap of indexed terms
+    /// with `product_id`, computed on the fly.
+    pub fn similar_products(&self, product_id: usize, limit: usize) -> Vec<&Product> {
+        self.filter_recommendable(self.content_neighbors(product_id), limit)
+            .iter()
+            .filter_map(|id| self.products.get(id))
+            .collect()
+    }
+
+    /// Caches the top `top_k` `similar_products` of every product. Product
+    /// mutations recompute only the rows sharing a term with the product.
+    pub fn precompute_similarity(&mut self, top_k: usize) {
+        let neighbors: HashMap<usize, Vec<usize>> = self.products.keys()
+            .map(|&id| (id, self.filter_recommendable(self.content_neighbors(id), top_k)))
+            .collect();
+        let rows_computed = neighbors.len();
+        self.similarity = Some(SimilarityMatrix { top_k, neighbors, rows_computed });
+    }
+
+    /// Products sharing at least one indexed term with `product_id`
+    /// (including itself): the only rows of the similarity matrix that a
+    /// change to it can affect. Empty when no matrix is cached.
+    fn content_related(&self, product_id: usize) -> HashSet<usize> {
+        if self.similarity.is_none() {
+            return HashSet::new();
+        }
+        let mut related: HashSet<usize> = self.hash_index.doc_terms.get(&product_id)
+            .into_iter()
+            .flat_map(|terms| terms.keys())
+            .filter_map(|t| self.hash_index.index.get(t))
+            .flatten()
+            .copied()
+            .collect();
+        related.insert(product_id);
+        related
+    }
+
+    fn refresh_similarity(&mut self, ids: HashSet<usize>) {
+        let Some(top_k) = self.similarity.as_ref().map(|m| m.top_k) else { return; };
+        let rows: Vec<(usize, Option<Vec<usize>>)> = ids.into_iter()
+            .map(|id| {
+                let row = self.products.contains_key(&id).then(|| self.filter_recommendable(self.content_neighbors(id), top_k));
+                (id, row)
+            })
+            .collect();
+
+        let matrix = self.similarity.as_mut().unwrap();
+        for (id, row) in rows {
+            match row {
+                Some(row) => {
+                    matrix.rows_computed += 1;
+                    matrix.neighbors.insert(id, row);
+                }
+                None => {
+                    matrix.neighbors.remove(&id);
+                }
+            }
+        }
+    }
+
+    #[cfg(any(test, feature = "invariants"))]
+    pub fn similarity_rows_computed(&self) -> usize {
+        self.similarity.as_ref().map_or(0, |m| m.rows_computed)
+    }
+
+    /// Reads from the precomputed matrix, falling back to `similar_products`
+    /// when it is missing, stale, or was built with a smaller `top_k`.
+    pub fn recommend_precomputed(&self, product_id: usize, limit: usize) -> Vec<&Product> {
+        match &self.similarity {
+            Some(matrix) if limit <= matrix.top_k => matrix.neighbors.get(&product_id)
+                .into_iter()
+                .flatten()
+                .take(limit)
+                .filter_map(|id| self.products.get(id))
+                .collect(),
+            _ => self.similar_products(product_id, limit),
+        }
+    }
+
+    pub fn has_precomputed_similarity(&self) -> bool {
+        self.similarity.is_some()
+    }
+
+    fn content_neighbors(&self, product_id: usize) -> Vec<usize> {
+        let Some(terms) = self.hash_index.doc_terms.get(&product_id) else { return Vec::new(); };
+        let candidates: HashSet<usize> = terms.keys()
+            .filter_map(|t| self.hash_index.index.get(t))
+            .flatten()
+            .copied()
+            .filter(|&id| id != product_id)
+            .collect();
+
+        let mut scored: Vec<(usize, f32)> = candidates.into_iter()
+            .map(|id| {
+                let other = &self.hash_index.doc_terms[&id];
+                let shared = terms.keys().filter(|t| other.contains_key(*t)).count();
+                (id, shared as f32 / (terms.len() + other.len() - shared) as f32)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scored.into_iter().map(|(id, _)| id).collect()
+    }
+
+    fn filter_recommendable(&self, ranked: Vec<usize>, limit: usize) -> Vec<usize> {
+        ranked.into_iter()
+            .filter(|id| self.products.get(id).is_some_and(|p| p.recommendable))
+            .take(limit)
+            .collect()
+    }
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn validate_product(p: Product) -> Result<Product, String> {
+    if p.name.trim().is_empty() {
+        return Err("missing name".into());
+    }
+    if p.category.trim().is_empty() {
+        return Err("missing category".into());
+    }
+    Ok(p)
+}
+
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    fields.push(current);
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+fn remove_posting(postings: &mut HashMap<String, HashSet<usize>>, term: &str, id: usize) {
+    if let Some(ids) = postings.get_mut(term) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            postings.remove(term);
+        }
+    }
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+fn stem_english(token: &str) -> String {
+    let len = token.chars().count();
+    let undouble = |stem: &str| -> String {
+        let mut chars: Vec<char> = stem.chars().collect();
+        let n = chars.len();
+        if n >= 2 && chars[n - 1] == chars[n - 2] && !"aeiouls".contains(chars[n - 1]) {
+            chars.pop();
+        }
+        chars.into_iter().collect()
+    };
+
+    if len > 4 && let Some(stem) = token.strip_suffix("ies") {
+        return format!("{}y", stem);
+    }
+    if len > 5 && let Some(stem) = token.strip_suffix("ing") {
+        return undouble(stem);
+    }
+    if len > 4 && let Some(stem) = token.strip_suffix("ed") {
+        return undouble(stem);
+    }
+    if len > 3 && !token.ends_with("ss") && let Some(stem) = token.strip_suffix('s') {
+        return stem.to_string();
+    }
+    token.to_string()
+}
+
+fn stem_portuguese(token: &str) -> String {
+    let len = token.chars().count();
+    if len <= 3 {
+        return token.to_string();
+    }
+    for (suffix, replacement) in [("ões", "ão"), ("ães", "ão"), ("ais", "al"), ("éis", "el"), ("eis", "el"), ("ns", "m")] {
+        if let Some(stem) = token.strip_suffix(suffix) {
+            return format!("{}{}", stem, replacement);
+        }
+    }
+    match token.strip_suffix('s') {
+        Some(stem) if stem.ends_with(|c: char| "aeiouáéíóúâêôãõ".contains(c)) => stem.to_string(),
+        _ => token.to_string(),
+    }
+}
+
+fn fold_char(c: char) -> char {
+    match c {
+        'á' | 'à' | 'â' | 'ã' | 'ä' => 'a',
+        'é' | 'è' | 'ê' | 'ë' => 'e',
+        'í' | 'ì' | 'î' | 'ï' => 'i',
+        'ó' | 'ò' | 'ô' | 'õ' | 'ö' => 'o',
+        'ú' | 'ù' | 'û' | 'ü' => 'u',
+        'ç' => 'c',
+        'ñ' => 'n',
+        _ => c,
+    }
+}
+
+/// Splits `query` into its plain words and its `-`-prefixed (negated) words.
+fn split_negated(query: &str) -> (Vec<&str>, Vec<&str>) {
+    query.split_whitespace().partition(|w| !(w.len() > 1 && w.starts_with('-')))
+}
+
+fn fold_accents(s: &str) -> String {
+    s.chars().map(fold_char).collect()
+}
+
+/// Slices `s` by byte offsets, widening `start` and `end` outwards to the
+/// nearest char boundaries so offsets inside a multi-byte char never panic.
+pub fn safe_slice(s: &str, start: usize, end: usize) -> &str {
+    let mut start = start.min(s.len());
+    let mut end = end.clamp(start, s.len());
+    while !s.is_char_boundary(start) {
+        start -= 1;
+    }
+    while !s.is_char_boundary(end) {
+        end += 1;
+    }
+    &s[start..end]
+}
+
+pub fn time_it<F, R>(label: &str, f: F) -> (R, Duration)
+where
+    F: FnOnce() -> R,
+{
+    let start = Instant::now();
+    let res = f();
+    let dur = start.elapsed();
+    println!("[METRIC] {}: {:?} ms", label, dur.as_millis());
+    (res, dur)
+}
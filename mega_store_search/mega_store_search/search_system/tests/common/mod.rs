@@ -0,0 +1,23 @@
+use search_system::{Catalog, Product};
+
+const BRANDS: [&str; 6] = ["Dell", "Lenovo", "Samsung", "Golden", "MarcaX", "AcessoriosPro"];
+const CATEGORIES: [&str; 5] = ["Eletrônicos", "Vestuário", "Pet Shop", "Acessórios", "Casa"];
+const KINDS: [&str; 8] = ["Notebook", "Monitor", "Camiseta", "Ração", "Capa", "Mouse", "Cadeira", "Teclado"];
+
+pub fn synthetic_product(i: usize) -> Product {
+    Product {
+        name: format!("{} {} Modelo {}", KINDS[i % KINDS.len()], BRANDS[i % BRANDS.len()], i),
+        brand: BRANDS[i % BRANDS.len()].into(),
+        category: CATEGORIES[i % CATEGORIES.len()].into(),
+        description: Some(format!("Item sintético número {} da série {}", i, i % 97)),
+        ..Default::default()
+    }
+}
+
+pub fn synthetic_catalog(n: usize) -> Catalog {
+    let mut catalog = Catalog::new();
+    for i in 0..n {
+        catalog.add_product(synthetic_product(i));
+    }
+    catalog
+}
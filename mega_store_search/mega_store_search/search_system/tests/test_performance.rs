@@ -0,0 +1,22 @@
+mod common;
+
+use search_system::time_it;
+use std::time::Duration;
+
+const CATALOG_SIZE: usize = 10_000;
+const INDEX_BUDGET: Duration = Duration::from_secs(30);
+const SEARCH_BUDGET: Duration = Duration::from_secs(2);
+
+#[test]
+fn test_indexing_and_search_stay_within_budget() {
+    let (catalog, indexing) = time_it("Indexing synthetic catalog", || common::synthetic_catalog(CATALOG_SIZE));
+    assert!(indexing < INDEX_BUDGET, "indexing {} products took {:?}", CATALOG_SIZE, indexing);
+
+    let (results, search) = time_it("Search tokens 'notebook dell'", || catalog.search_tokens("notebook dell"));
+    assert!(!results.is_empty());
+    assert!(search < SEARCH_BUDGET, "token search took {:?}", search);
+
+    let (ranked, search) = time_it("Ranked search 'notebook dell'", || catalog.search_ranked("notebook dell", 10));
+    assert_eq!(ranked.len(), 10);
+    assert!(search < SEARCH_BUDGET, "ranked search took {:?}", search);
+}
@@ -1,4 +1,28 @@
 use hashbrown::HashMap;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use search_system::{safe_slice, BTreeIndex, Catalog, CatalogError, CatalogSnapshot, CatalogStore, FileStore, EmptyQueryPolicy, Field, FuzzyDistance, ImportMode, LanguageRules, LogEvent, NormalizeStep, OrderedBackend, OrderedIndex, Product, QueryError, QueryLogEntry, QueryNode, RatingConfig, ReasonCode, RecommendDiagnostic, Relaxation, RecGraph, RecommendMode, SearchTier, SortedVecIndex, TokenizerConfig};
+
+fn product(name: &str, brand: &str, category: &str, description: &str) -> Product {
+    Product {
+        id: 0,
+        name: name.into(),
+        brand: brand.into(),
+        category: category.into(),
+        description: Some(description.into()),
+        ..Default::default()
+    }
+}
+
+fn sample_catalog() -> Catalog {
+    let mut catalog = Catalog::new();
+    catalog.add_product(product("Notebook Dell Inspiron 15", "Dell", "Eletrônicos", "Intel i5, 8GB RAM"));
+    catalog.add_product(product("Notebook Dell XPS 13", "Dell", "Eletrônicos", "Performance e portabilidade"));
+    catalog.add_product(product("Camiseta Polo Masculina", "MarcaX", "Vestuário", "Algodão Pima"));
+    catalog.add_product(product("Ração Golden Adulto", "Golden", "Pet Shop", "Proteína e vitaminas"));
+    catalog.add_product(product("Capa para Notebook 15", "AcessoriosPro", "Acessórios", "Resistente à água"));
+    catalog
+}
 
 #[test]
 fn test_busca_produto_existente() {
@@ -7,3 +31,1330 @@ fn test_busca_produto_existente() {
 
     assert_eq!(catalogo.get("Notebook Dell"), Some(&"Eletrônicos"));
 }
+
+#[test]
+fn test_search_envelope_reports_truncation() {
+    let catalog = sample_catalog();
+
+    let result = catalog.search("notebook", 2);
+    assert_eq!(result.products.len(), 2);
+    assert_eq!(result.total, 3);
+    // The page is cut from the relevance ranking, not from id order.
+    let ranked: Vec<usize> = catalog.search_ranked("notebook", 2).iter().map(|(p, _)| p.id).collect();
+    assert_eq!(result.products.iter().map(|p| p.id).collect::<Vec<_>>(), ranked);
+    assert_eq!(ranked, vec![2, 5]);
+    assert!(result.total > result.products.len());
+    assert!(result.truncated);
+
+    let result = catalog.search("notebook", 10);
+    assert_eq!(result.total, result.products.len());
+    assert!(!result.truncated);
+}
+
+#[test]
+fn test_average_document_length_survives_mutations() {
+    let mut catalog = sample_catalog();
+    catalog.check_invariants();
+
+    catalog.remove_product(3);
+    catalog.check_invariants();
+
+    assert!(catalog.update_product(1, product("Notebook Dell Inspiron 15 Gamer Edition", "Dell", "Eletrônicos", "")));
+    catalog.check_invariants();
+
+    catalog.add_product(product("Mouse", "Logitech", "Acessórios", ""));
+    catalog.remove_product(5);
+    assert!(!catalog.update_product(42, product("Inexistente", "X", "Y", "")));
+    catalog.check_invariants();
+
+    // Inspiron (8) + XPS (9) + Ração (9) + Mouse (3)
+    assert!((catalog.average_document_length() - 29.0 / 4.0).abs() < f32::EPSILON);
+
+    for id in [1, 2, 4, 6] {
+        catalog.remove_product(id);
+    }
+    catalog.check_invariants();
+    assert_eq!(catalog.average_document_length(), 0.0);
+}
+
+#[test]
+fn test_pinned_product_comes_first() {
+    let mut catalog = sample_catalog();
+    let organic: Vec<usize> = catalog.search_ranked("notebook", 10).iter().map(|(p, _)| p.id).collect();
+    assert_eq!(organic, vec![2, 5, 1]);
+
+    catalog.pin_product("  Notebook ", 5);
+    catalog.pin_product("notebook", 5);
+    let ranked: Vec<usize> = catalog.search_ranked("NOTEBOOK", 10).iter().map(|(p, _)| p.id).collect();
+    assert_eq!(ranked, vec![5, 2, 1]);
+
+    let partial: Vec<usize> = catalog.search_ranked("notebook dell", 10).iter().map(|(p, _)| p.id).collect();
+    assert_eq!(partial, vec![2, 1]);
+
+    catalog.pin_product("dell", 4);
+    let editorial: Vec<usize> = catalog.search_ranked("dell", 2).iter().map(|(p, _)| p.id).collect();
+    assert_eq!(editorial, vec![4, 2]);
+}
+
+#[test]
+fn test_tiered_search_orders_exact_prefix_fuzzy() {
+    let mut catalog = Catalog::new();
+    catalog.add_product(product("Tecla Extra", "KeyCo", "Periféricos", ""));
+    catalog.add_product(product("Teclado Gamer", "KeyCo", "Periféricos", ""));
+    catalog.add_product(product("Teclas Coloridas", "KeyCo", "Periféricos", ""));
+    catalog.add_product(product("Mouse Sem Fio", "KeyCo", "Periféricos", ""));
+
+    assert!(catalog.search_tokens("teclad").is_empty());
+
+    let tiered: Vec<(usize, SearchTier)> = catalog.search_tiered("teclad", 10).iter()
+        .map(|(p, tier)| (p.id, *tier))
+        .collect();
+    assert_eq!(tiered, vec![(2, SearchTier::Prefix), (1, SearchTier::Fuzzy), (3, SearchTier::Fuzzy)]);
+
+    let limited = catalog.search_tiered("teclad", 2);
+    assert_eq!(limited.len(), 2);
+    assert_eq!(limited[1].1, SearchTier::Fuzzy);
+
+    let exact: Vec<(usize, SearchTier)> = catalog.search_tiered("tecla", 10).iter()
+        .map(|(p, tier)| (p.id, *tier))
+        .collect();
+    assert_eq!(exact, vec![(1, SearchTier::Exact), (2, SearchTier::Prefix), (3, SearchTier::Prefix)]);
+}
+
+#[test]
+fn test_tiered_exact_requires_every_token() {
+    let catalog = sample_catalog();
+    let tiered: Vec<(usize, SearchTier)> = catalog.search_tiered("dell notebok", 10).iter()
+        .map(|(p, tier)| (p.id, *tier))
+        .collect();
+    assert_eq!(tiered, vec![(1, SearchTier::Fuzzy), (2, SearchTier::Fuzzy)]);
+}
+
+#[test]
+fn test_search_excluding_categories() {
+    let catalog = sample_catalog();
+
+    let mut ids: Vec<usize> = catalog.search_excluding_categories("notebook", &["ACESSÓRIOS ".to_string()])
+        .iter()
+        .map(|p| p.id)
+        .collect();
+    ids.sort_unstable();
+    assert_eq!(ids, vec![1, 2]);
+
+    let mut all: Vec<usize> = catalog.search_excluding_categories("notebook", &[]).iter().map(|p| p.id).collect();
+    let mut plain: Vec<usize> = catalog.search_tokens("notebook").iter().map(|p| p.id).collect();
+    all.sort_unstable();
+    plain.sort_unstable();
+    assert_eq!(all, plain);
+}
+
+#[test]
+fn test_public_tokenizer_matches_indexed_terms() {
+    let config = TokenizerConfig {
+        stop_words: ["Para".to_string()].into_iter().collect(),
+        ..Default::default()
+    }.with_accent_folding();
+    let mut catalog = Catalog::with_tokenizer(config);
+    let capa = product("Capa para Notebook 15", "AcessoriosPro", "Acessórios", "Resistente à água");
+    let text = format!("{} {} {} {}", capa.name, capa.brand, capa.category, capa.description.clone().unwrap());
+    catalog.add_product(capa);
+
+    let mut tokens = catalog.tokenize(&text);
+    tokens.sort_unstable();
+    tokens.dedup();
+    assert_eq!(tokens, catalog.product_terms(1));
+    assert_eq!(catalog.tokenize("Acessórios PARA"), vec!["acessorios"]);
+    assert_eq!(catalog.search_tokens("acessórios").len(), 1);
+}
+
+#[test]
+fn test_recommendation_cache_separates_modes() {
+    let mut catalog = sample_catalog();
+    catalog.add_product(product("Mouse Sem Fio", "Logitech", "Acessórios", ""));
+    for (a, b) in [(1, 2), (1, 3), (2, 3), (3, 4), (3, 5), (3, 6)] {
+        catalog.add_recommendation_edge(a, b);
+    }
+
+    let jaccard: Vec<usize> = catalog.recommend_with(1, 1, RecommendMode::Jaccard).iter().map(|p| p.id).collect();
+    assert_eq!(jaccard, vec![2]);
+    assert_eq!(catalog.recommendation_cache_len(), 1);
+
+    let degree: Vec<usize> = catalog.recommend_with(1, 1, RecommendMode::Degree).iter().map(|p| p.id).collect();
+    assert_eq!(degree, vec![3]);
+    assert_eq!(catalog.recommendation_cache_len(), 2);
+
+    let cached: Vec<usize> = catalog.recommend_for(1, 1).iter().map(|p| p.id).collect();
+    assert_eq!(cached, vec![3]);
+    assert_eq!(catalog.recommendation_cache_len(), 2);
+
+    catalog.add_recommendation_edge(2, 4);
+    assert_eq!(catalog.recommendation_cache_len(), 0);
+}
+
+#[test]
+fn test_graph_summary() {
+    let mut graph = RecGraph::new();
+    for (a, b) in [(1, 2), (1, 3), (2, 3), (3, 4), (4, 3)] {
+        graph.add_edge(a, b);
+    }
+
+    let summary = graph.summary();
+    assert_eq!(summary.nodes, 4);
+    assert_eq!(summary.edges, 4);
+    assert!((summary.avg_degree - 2.0).abs() < f32::EPSILON);
+    assert_eq!(summary.max_degree, 3);
+    assert!(summary.connected);
+
+    graph.add_edge(5, 6);
+    let summary = graph.summary();
+    assert_eq!(summary.nodes, 6);
+    assert_eq!(summary.edges, 5);
+    assert!(!summary.connected);
+    assert_eq!(graph.connected_components(), vec![vec![1, 2, 3, 4], vec![5, 6]]);
+}
+
+#[test]
+fn test_search_restricted_to_fields() {
+    let mut catalog = sample_catalog();
+    catalog.add_product(product("Suporte para Notebook", "ErgoMax", "Acessórios", "Compatível com Dell e Lenovo"));
+
+    let mut all: Vec<usize> = catalog.search_tokens("dell").iter().map(|p| p.id).collect();
+    all.sort_unstable();
+    assert_eq!(all, vec![1, 2, 6]);
+
+    let mut brand_only: Vec<usize> = catalog.search_in_fields("dell", &[Field::Brand]).iter().map(|p| p.id).collect();
+    brand_only.sort_unstable();
+    assert_eq!(brand_only, vec![1, 2]);
+
+    let mut every_field: Vec<usize> = catalog.search_in_fields("dell", &Field::ALL).iter().map(|p| p.id).collect();
+    every_field.sort_unstable();
+    assert_eq!(every_field, all);
+
+    let mut mixed: Vec<usize> = catalog.search_in_fields("notebook lenovo", &[Field::Name, Field::Description])
+        .iter()
+        .map(|p| p.id)
+        .collect();
+    mixed.sort_unstable();
+    assert_eq!(mixed, vec![6]);
+}
+
+#[test]
+fn test_query_log_is_bounded_and_ordered() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let mut catalog = sample_catalog();
+    catalog.search_tokens("dell");
+    assert!(catalog.export_query_log().is_empty());
+
+    let tick = AtomicU64::new(100);
+    catalog.enable_query_log_with_clock(3, move || tick.fetch_add(1, Ordering::SeqCst));
+
+    catalog.search_tokens("Notebook");
+    catalog.search_tokens("  DELL ");
+    catalog.search("golden", 10);
+    catalog.search_tokens("inexistente");
+
+    let entry = |query: &str, result_count, timestamp| QueryLogEntry {
+        query: query.into(),
+        result_count,
+        timestamp,
+        session: None,
+        event: LogEvent::Query,
+    };
+    assert_eq!(catalog.export_query_log(), vec![
+        entry("dell", 2, 101),
+        entry("golden", 1, 102),
+        entry("inexistente", 0, 103),
+    ]);
+
+    // Negated words stay distinguishable from the opposite query.
+    catalog.search_tokens("Notebook -XPS");
+    catalog.search_tokens("notebook xps");
+    let logged: Vec<String> = catalog.export_query_log().into_iter().map(|e| e.query).collect();
+    assert_eq!(logged[1..], ["notebook -xps".to_string(), "notebook xps".to_string()]);
+
+    catalog.disable_query_log();
+    assert!(catalog.export_query_log().is_empty());
+}
+
+#[test]
+fn test_or_search_rewards_more_matched_tokens() {
+    let catalog = sample_catalog();
+
+    let ranked: Vec<usize> = catalog.search_or_scored("notebook dell inspiron", 10).iter().map(|(p, _)| p.id).collect();
+    assert_eq!(ranked, vec![1, 2, 5]);
+
+    let scored = catalog.search_or_scored("capa golden notebook", 10);
+    assert_eq!(scored.len(), 4);
+    assert_eq!(scored[0].0.id, 5);
+    assert!(scored.windows(2).all(|w| w[0].1 >= w[1].1));
+
+    assert_eq!(catalog.search_or_scored("notebook", 1).len(), 1);
+}
+
+#[test]
+fn test_non_recommendable_products_still_bridge() {
+    let mut catalog = sample_catalog();
+    catalog.add_recommendation_edge(1, 3);
+    catalog.add_recommendation_edge(3, 4);
+
+    let ids = |products: Vec<&Product>| products.iter().map(|p| p.id).collect::<Vec<_>>();
+    assert_eq!(ids(catalog.recommend_for(1, 5)), vec![3]);
+    assert_eq!(ids(catalog.recommend_multi_hop(1, 2, 5)), vec![3, 4]);
+
+    let mut hidden = catalog.get_product(3).unwrap().clone();
+    hidden.recommendable = false;
+    assert!(catalog.update_product(3, hidden));
+
+    assert!(catalog.recommend_for(1, 5).is_empty());
+    assert_eq!(ids(catalog.recommend_multi_hop(1, 2, 5)), vec![4]);
+    assert_eq!(ids(catalog.search_tokens("camiseta")), vec![3]);
+}
+
+#[test]
+fn test_search_collapses_variants_by_group() {
+    let mut catalog = Catalog::new();
+    for (name, description) in [
+        ("Camiseta Básica Azul", "Algodão"),
+        ("Camiseta Básica", "Algodão"),
+        ("Camiseta Básica Vermelha Estampada", "Algodão"),
+    ] {
+        let mut variant = product(name, "MarcaX", "Vestuário", description);
+        variant.group_id = Some("camiseta-basica".into());
+        catalog.add_product(variant);
+    }
+    catalog.add_product(product("Camiseta Polo", "MarcaX", "Vestuário", "Algodão"));
+    catalog.add_product(product("Camiseta Regata", "MarcaX", "Vestuário", "Algodão"));
+
+    let collapsed: Vec<(usize, usize)> = catalog.search_collapsed("camiseta", 10).iter()
+        .map(|(p, count)| (p.id, *count))
+        .collect();
+    assert_eq!(collapsed.len(), 3);
+    assert!(collapsed.contains(&(2, 3)));
+    assert!(collapsed.contains(&(4, 1)));
+    assert!(collapsed.contains(&(5, 1)));
+    assert_eq!(catalog.search_ranked("camiseta", 10).len(), 5);
+}
+
+#[test]
+fn test_authority_weight_flips_ranking() {
+    let mut catalog = Catalog::new();
+    catalog.add_product(product("Notebook Básico", "Dell", "Eletrônicos", ""));
+    catalog.add_product(product("Notebook Gamer Ultra Fino Preto Edição Especial", "Dell", "Eletrônicos", "Tela 15 polegadas"));
+    catalog.add_product(product("Mouse Gamer", "Logitech", "Acessórios", ""));
+    catalog.add_product(product("Mochila", "Samsonite", "Acessórios", ""));
+    catalog.add_product(product("Headset", "HyperX", "Acessórios", ""));
+    for accessory in [3, 4, 5] {
+        catalog.add_recommendation_edge(2, accessory);
+    }
+
+    let order = |relevance: f32, authority: f32| -> Vec<usize> {
+        catalog.search_by_authority("notebook", 10, relevance, authority).iter().map(|(p, _)| p.id).collect()
+    };
+    assert_eq!(order(1.0, 0.0), vec![1, 2]);
+    assert_eq!(order(1.0, 1.0), vec![2, 1]);
+    assert_eq!(order(0.0, 1.0), vec![2, 1]);
+
+    let scored = catalog.search_by_authority("notebook", 10, 1.0, 1.0);
+    assert!(scored.iter().all(|(_, score)| (0.0..=2.0).contains(score)));
+}
+
+#[test]
+fn test_safe_slice_snaps_to_char_boundaries() {
+    assert_eq!(safe_slice("ção", 1, 2), "ç");
+    assert_eq!(safe_slice("ção", 0, 3), "çã");
+    assert_eq!(safe_slice("água", 1, 1), "á");
+    assert_eq!(safe_slice("água", 10, 20), "");
+
+    for text in ["ção", "água", "Resistente à água", "Ração Golden Adulto"] {
+        for start in 0..=text.len() + 1 {
+            for end in 0..=text.len() + 1 {
+                let slice = safe_slice(text, start, end);
+                assert!(text.contains(slice));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_snippet_handles_multibyte_boundaries() {
+    let catalog = sample_catalog();
+    assert_eq!(catalog.snippet(5, "água", 2).as_deref(), Some("à água"));
+    assert_eq!(catalog.snippet(5, "ÁGUA", 1).as_deref(), Some("água"));
+    assert_eq!(catalog.snippet(3, "algodão", 0).as_deref(), Some("Algodão"));
+    assert_eq!(catalog.snippet(3, "inexistente", 5), None);
+
+    let mut folded = Catalog::with_tokenizer(TokenizerConfig::default().with_accent_folding());
+    folded.add_product(product("Ração Golden", "Golden", "Pet Shop", "Proteína e nutrição"));
+    assert_eq!(folded.snippet(1, "nutricao", 0).as_deref(), Some("nutrição"));
+    assert_eq!(folded.snippet(1, "proteina", 3).as_deref(), Some("Proteína e"));
+}
+
+#[test]
+fn test_trending_skips_edges_to_unknown_ids() {
+    let mut catalog = sample_catalog();
+    catalog.set_trending_size(2);
+    let trending = |catalog: &Catalog| catalog.trending().iter().map(|p| p.id).collect::<Vec<_>>();
+
+    catalog.add_recommendation_edges(&[(100, 101), (100, 102), (101, 102), (1, 2)]);
+    assert_eq!(trending(&catalog), vec![1, 2]);
+
+    // Edges recorded before the product existed count once it is added.
+    catalog.add_recommendation_edges(&[(6, 3), (6, 4), (6, 5)]);
+    assert_eq!(trending(&catalog), vec![1, 2]);
+    catalog.add_product(product("Mouse Gamer", "Logitech", "Eletrônicos", "RGB"));
+    assert_eq!(trending(&catalog), vec![6, 1]);
+}
+
+#[test]
+fn test_trending_matches_recomputation() {
+    let mut catalog = sample_catalog();
+    catalog.set_trending_size(2);
+
+    let recompute = |catalog: &Catalog| -> Vec<usize> {
+        let mut scores: Vec<(usize, usize)> = (1..=5)
+            .map(|id| (id, catalog.graph().degree(id) + catalog.popularity(id)))
+            .filter(|&(_, score)| score > 0)
+            .collect();
+        scores.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        scores.into_iter().take(2).map(|(id, _)| id).collect()
+    };
+    let trending = |catalog: &Catalog| catalog.trending().iter().map(|p| p.id).collect::<Vec<_>>();
+
+    catalog.add_recommendation_edge(1, 2);
+    catalog.add_recommendation_edge(1, 5);
+    assert_eq!(trending(&catalog), vec![1, 2]);
+    assert_eq!(trending(&catalog), recompute(&catalog));
+
+    catalog.increment_popularity(4, 3);
+    assert_eq!(trending(&catalog), vec![4, 1]);
+    assert_eq!(trending(&catalog), recompute(&catalog));
+
+    catalog.remove_recommendation_edge(1, 2);
+    catalog.remove_recommendation_edge(1, 5);
+    catalog.add_recommendation_edge(3, 5);
+    catalog.add_recommendation_edge(2, 5);
+    assert_eq!(trending(&catalog), vec![4, 5]);
+    assert_eq!(trending(&catalog), recompute(&catalog));
+
+    catalog.remove_product(4);
+    assert_eq!(trending(&catalog), vec![5, 2]);
+    assert_eq!(trending(&catalog), recompute(&catalog));
+}
+
+#[test]
+fn test_related_queries_from_sessions() {
+    let mut catalog = sample_catalog();
+    catalog.enable_query_log(100);
+
+    catalog.set_query_session(Some("a"));
+    catalog.search_tokens("notebook");
+    catalog.search_tokens("capa");
+    catalog.search_tokens("dell");
+    catalog.search_tokens("Notebook");
+
+    catalog.set_query_session(Some("b"));
+    catalog.search_tokens("notebook");
+    catalog.search_tokens("capa");
+    catalog.search_tokens("golden");
+
+    catalog.set_query_session(None);
+    catalog.search_tokens("notebook");
+    catalog.search_tokens("camiseta");
+
+    assert_eq!(catalog.related_queries("NOTEBOOK", 10), vec![
+        ("capa".to_string(), 2),
+        ("dell".to_string(), 1),
+        ("golden".to_string(), 1),
+    ]);
+    assert_eq!(catalog.related_queries("golden", 1), vec![("capa".to_string(), 1)]);
+    assert!(catalog.related_queries("camiseta", 10).is_empty());
+}
+
+#[test]
+fn test_and_search_scored_by_idf_and_fields() {
+    let mut catalog = sample_catalog();
+    catalog.add_product(product("Suporte para Notebook", "ErgoMax", "Acessórios", "Compatível com Dell e Lenovo"));
+
+    let ranked: Vec<usize> = catalog.search_tokens_scored("dell", 10).iter().map(|(p, _)| p.id).collect();
+    assert_eq!(ranked, vec![2, 1, 6]);
+
+    let ranked: Vec<usize> = catalog.search_tokens_scored("notebook dell", 10).iter().map(|(p, _)| p.id).collect();
+    assert_eq!(ranked, vec![2, 1, 6]);
+
+    for _ in 0..5 {
+        let again: Vec<usize> = catalog.search_tokens_scored("notebook", 10).iter().map(|(p, _)| p.id).collect();
+        assert_eq!(again, vec![2, 5, 1, 6]);
+    }
+}
+
+#[test]
+fn test_import_report_counts() {
+    let csv = "name,brand,category,description\n\
+        Notebook Dell Vostro,Dell,Eletrônicos,\"Intel i7, 16GB\"\n\
+        Notebook Dell Inspiron 15,Dell,Eletrônicos,Repetido\n\
+        ,SemNome,Eletrônicos,\n\
+        Mouse Sem Fio,Logitech,Acessórios\n\
+        Mouse Sem Fio,Logitech,Acessórios,Outro\n\
+        Teclado Mecânico,Redragon,Periféricos,\n";
+
+    let mut catalog = sample_catalog();
+    let report = catalog.import_csv(csv, ImportMode::Lenient).unwrap();
+    assert_eq!(report.processed, 6);
+    assert_eq!(report.added, 3);
+    assert_eq!(report.duplicates, 1);
+    assert_eq!(report.rejected.iter().map(|r| r.line).collect::<Vec<_>>(), vec![4, 5]);
+    assert_eq!(catalog.search_tokens("vostro")[0].description.as_deref(), Some("Intel i7, 16GB"));
+
+    let mut strict = sample_catalog();
+    match strict.import_csv(csv, ImportMode::Strict) {
+        Err(CatalogError::Invalid { line, .. }) => assert_eq!(line, 4),
+        other => panic!("expected invalid record error, got {:?}", other),
+    }
+    assert!(strict.search_tokens("vostro").is_empty());
+
+    let jsonl = r#"{"name": "Ração Premium", "brand": "Golden", "category": "Pet Shop"}
+{"name": "Ração Golden Adulto", "brand": "Golden", "category": "Pet Shop"}
+{"name": "Coleira", "brand": 42}
+
+{"name": "Coleira", "brand": "PetPro", "category": "Pet Shop", "description": "Nylon"}"#;
+    let report = catalog.import_json_lines(jsonl, ImportMode::Lenient).unwrap();
+    assert_eq!(report.processed, 4);
+    assert_eq!(report.added, 2);
+    assert_eq!(report.duplicates, 1);
+    assert_eq!(report.rejected.len(), 1);
+    assert_eq!(report.rejected[0].line, 3);
+}
+
+#[test]
+fn test_capacity_hints_do_not_change_graph() {
+    let edges: Vec<(usize, usize)> = (1..200).flat_map(|a| [(a, (a * 7) % 200 + 1), (a, (a * 13) % 200 + 1)]).collect();
+
+    let mut plain = RecGraph::new();
+    for &(a, b) in &edges {
+        plain.add_edge(a, b);
+    }
+    let mut hinted = RecGraph::with_capacity(200, 4);
+    hinted.add_edges(&edges);
+
+    assert_eq!(plain.summary(), hinted.summary());
+    for id in 1..=200 {
+        assert_eq!(plain.neighbors(id), hinted.neighbors(id));
+        assert_eq!(plain.recommend(id, 5), hinted.recommend(id, 5));
+    }
+
+    let mut catalog = Catalog::with_capacity(10, 2);
+    for i in 0..5 {
+        catalog.add_product(product(&format!("Produto {}", i), "Marca", "Categoria", ""));
+    }
+    catalog.add_recommendation_edges(&[(1, 2), (1, 3), (2, 3)]);
+    assert_eq!(catalog.graph().summary().edges, 3);
+    assert_eq!(catalog.recommend_for(1, 5).len(), 2);
+}
+
+#[test]
+fn test_smart_search_falls_back_to_prefix() {
+    let catalog = sample_catalog();
+    assert!(catalog.search_tokens("noteb").is_empty());
+
+    let (results, tier) = catalog.search_smart("Noteb", 10);
+    let mut ids: Vec<usize> = results.iter().map(|p| p.id).collect();
+    ids.sort_unstable();
+    assert_eq!(ids, vec![1, 2]);
+    assert_eq!(tier, Some(SearchTier::Prefix));
+
+    let (results, tier) = catalog.search_smart("golden", 10);
+    assert_eq!(results.len(), 1);
+    assert_eq!(tier, Some(SearchTier::Exact));
+
+    let (results, tier) = catalog.search_smart("xyz", 10);
+    assert!(results.is_empty());
+    assert_eq!(tier, None);
+}
+
+#[test]
+fn test_language_aware_stemming() {
+    let config = TokenizerConfig::default()
+        .with_language("pt", LanguageRules::portuguese())
+        .with_language("en", LanguageRules::english());
+    let mut catalog = Catalog::with_tokenizer(config);
+
+    let mut shoes = product("The Running Shoes", "Nike", "Sports", "");
+    shoes.lang = Some("en".into());
+    catalog.add_product(shoes);
+    let mut tenis = product("Tênis Running para Corridas", "Nike", "Esportes", "");
+    tenis.lang = Some("pt".into());
+    catalog.add_product(tenis);
+    catalog.add_product(product("Camisetas Running", "Nike", "Vestuário", ""));
+
+    let en_terms = catalog.product_terms(1);
+    assert!(en_terms.contains(&"run".to_string()));
+    assert!(en_terms.contains(&"shoe".to_string()));
+    assert!(!en_terms.contains(&"the".to_string()));
+
+    let pt_terms = catalog.product_terms(2);
+    assert!(pt_terms.contains(&"running".to_string()));
+    assert!(pt_terms.contains(&"corrida".to_string()));
+    assert!(!pt_terms.contains(&"para".to_string()));
+
+    assert!(catalog.product_terms(3).contains(&"camiseta".to_string()));
+
+    let ids = |products: Vec<&Product>| {
+        let mut ids: Vec<usize> = products.iter().map(|p| p.id).collect();
+        ids.sort_unstable();
+        ids
+    };
+    assert_eq!(ids(catalog.search_tokens_lang("runs", Some("en"))), vec![1]);
+    assert_eq!(ids(catalog.search_tokens_lang("running", Some("pt"))), vec![2, 3]);
+    assert_eq!(ids(catalog.search_tokens_lang("running", None)), vec![2, 3]);
+    assert_eq!(catalog.tokenize_lang("Parties and Shoes", Some("en")), vec!["party", "shoe"]);
+}
+
+#[test]
+fn test_followup_searches_after_product_view() {
+    let mut catalog = sample_catalog();
+    catalog.enable_query_log(100);
+
+    catalog.set_query_session(Some("a"));
+    catalog.search_tokens("capa");
+    catalog.record_view(1);
+    catalog.search_tokens("Capa");
+    catalog.search_tokens("capa");
+    catalog.search_tokens("mouse");
+
+    catalog.set_query_session(Some("b"));
+    catalog.record_view(1);
+    catalog.search_tokens("capa notebook");
+    catalog.search_tokens("capa");
+
+    catalog.set_query_session(Some("c"));
+    catalog.record_view(2);
+    catalog.search_tokens("dell");
+
+    assert_eq!(catalog.followup_searches(1, 10), vec![
+        ("capa".to_string(), 2),
+        ("capa notebook".to_string(), 1),
+        ("mouse".to_string(), 1),
+    ]);
+    assert_eq!(catalog.followup_searches(2, 10), vec![("dell".to_string(), 1)]);
+    assert!(catalog.followup_searches(3, 10).is_empty());
+    assert!(catalog.related_queries("capa", 10).iter().all(|(q, _)| !q.is_empty()));
+}
+
+#[test]
+fn test_relaxed_search_drops_tokens_until_a_match() {
+    let catalog = sample_catalog();
+    let relaxed = |query: &str| {
+        let (results, relaxation) = catalog.search_relaxed(query, 10);
+        (results.iter().map(|p| p.id).collect::<Vec<_>>(), relaxation)
+    };
+
+    assert_eq!(relaxed("notebook dell"), (vec![1, 2], Relaxation::None));
+    assert_eq!(relaxed("notebook dell camiseta"), (vec![1, 2], Relaxation::DroppedTokens(1)));
+    assert_eq!(relaxed("dell golden camiseta"), (vec![1, 2], Relaxation::DroppedTokens(2)));
+    assert_eq!(relaxed("notebook golden"), (vec![1, 2, 5], Relaxation::DroppedTokens(1)));
+    assert_eq!(relaxed("dell qwerty zzz"), (vec![1, 2], Relaxation::DroppedTokens(2)));
+    assert_eq!(relaxed("camis xyzzy"), (vec![3], Relaxation::Prefix));
+    assert_eq!(relaxed("goldan"), (vec![4], Relaxation::Fuzzy));
+    assert_eq!(relaxed("qwerty"), (vec![], Relaxation::Exhausted));
+    assert_eq!(relaxed("?!"), (vec![], Relaxation::Exhausted));
+
+    let long_query = (0..60).map(|i| format!("desconhecido{i}")).collect::<Vec<_>>().join(" ") + " golden";
+    assert_eq!(relaxed(&long_query), (vec![4], Relaxation::DroppedTokens(60)));
+}
+
+#[test]
+fn test_price_histogram_buckets() {
+    let mut catalog = sample_catalog();
+    for (id, price) in [(1, 3500.0), (2, 8999.9), (5, 99.9)] {
+        let mut p = catalog.get_product(id).unwrap().clone();
+        p.price = Some(price);
+        catalog.update_product(id, p);
+    }
+    catalog.add_product(product("Notebook Usado", "Genérico", "Eletrônicos", ""));
+
+    let buckets = [(0.0, 100.0), (100.0, 500.0), (500.0, 5000.0), (5000.0, f64::INFINITY), (0.0, 5000.0)];
+    assert_eq!(catalog.price_histogram("notebook", &buckets), vec![1, 0, 1, 1, 2]);
+    assert_eq!(catalog.price_histogram("dell", &buckets[..2]), vec![0, 0]);
+    assert_eq!(catalog.price_histogram("notebook", &[(99.9, 100.0), (3500.0, 3500.0)]), vec![1, 0]);
+}
+
+#[test]
+fn test_recommend_filtered_by_edge_reason() {
+    let mut catalog = sample_catalog();
+    catalog.add_recommendation_edge(1, 2);
+    catalog.add_recommendation_edge_with_reason(1, 5, ReasonCode::CoPurchase);
+    catalog.add_recommendation_edge_with_reason(1, 4, ReasonCode::CoView);
+    catalog.add_recommendation_edge_with_reason(4, 1, ReasonCode::CoPurchase);
+
+    let ids = |products: Vec<&Product>| products.iter().map(|p| p.id).collect::<Vec<_>>();
+    assert_eq!(ids(catalog.recommend_by_reason(1, 10, &[ReasonCode::CoPurchase])), vec![4, 5]);
+    assert_eq!(ids(catalog.recommend_by_reason(1, 10, &[ReasonCode::Manual])), vec![2]);
+    assert_eq!(ids(catalog.recommend_by_reason(1, 10, &[ReasonCode::Manual, ReasonCode::CoView])), vec![2, 4]);
+    assert!(catalog.recommend_by_reason(1, 10, &[ReasonCode::Editorial]).is_empty());
+    assert_eq!(catalog.graph().edge_reasons(4, 1), vec![ReasonCode::CoPurchase, ReasonCode::CoView]);
+
+    catalog.remove_recommendation_edge(1, 4);
+    assert!(catalog.graph().edge_reasons(1, 4).is_empty());
+    assert_eq!(ids(catalog.recommend_by_reason(1, 10, &[ReasonCode::CoPurchase])), vec![5]);
+
+    // Reweighting an existing edge must not tag it as Manual.
+    catalog.add_weighted_recommendation_edge(1, 5, 3.0);
+    assert_eq!(catalog.graph().edge_reasons(1, 5), vec![ReasonCode::CoPurchase]);
+    assert_eq!(catalog.graph().edge_weight(1, 5), Some(3.0));
+    assert_eq!(ids(catalog.recommend_by_reason(1, 10, &[ReasonCode::Manual])), vec![2]);
+    catalog.add_weighted_recommendation_edge(1, 3, 2.0);
+    assert_eq!(catalog.graph().edge_reasons(1, 3), vec![ReasonCode::Manual]);
+}
+
+#[test]
+fn test_predict_links_prefers_shared_neighbors() {
+    let mut graph = RecGraph::new();
+    for (a, b) in [(1, 3), (1, 4), (2, 3), (2, 4), (3, 4), (4, 5), (5, 6)] {
+        graph.add_edge(a, b);
+    }
+
+    let predicted = graph.predict_links(10);
+    assert_eq!((predicted[0].0, predicted[0].1), (1, 2));
+    assert!(predicted[0].2 > predicted[1].2);
+    assert!(predicted.iter().all(|&(a, b, _)| !graph.neighbors(a).contains(&b)));
+    assert!(predicted.iter().any(|&(a, b, _)| (a, b) == (4, 6)));
+    assert_eq!(graph.predict_links(1).len(), 1);
+}
+
+#[test]
+fn test_dedupe_by_name_toggle() {
+    let mut catalog = sample_catalog();
+    catalog.add_product(product("notebook dell  XPS 13", "Dell", "Eletrônicos", "Performance e portabilidade"));
+
+    let ids = |hits: Vec<(&Product, f32)>| hits.iter().map(|(p, _)| p.id).collect::<Vec<_>>();
+    assert_eq!(ids(catalog.search_ranked("xps", 10)), vec![2, 6]);
+    assert_eq!(catalog.search("xps", 10).total, 2);
+
+    catalog.set_dedupe_by_name(true);
+    assert_eq!(ids(catalog.search_ranked("xps", 10)), vec![2]);
+    assert_eq!(ids(catalog.search_tokens_scored("xps", 10)), vec![2]);
+    assert_eq!(ids(catalog.search_or_scored("xps", 10)), vec![2]);
+    let envelope = catalog.search("xps", 10);
+    assert_eq!(envelope.total, 1);
+    assert_eq!(envelope.products[0].id, 2);
+    assert_eq!(catalog.search_ranked("notebook", 10).len(), 3);
+    assert_eq!(ids(catalog.search_by_authority("xps", 10, 1.0, 0.0)), vec![2]);
+    let smart_ids = |query: &str| catalog.search_smart(query, 10).0.iter().map(|p| p.id).collect::<Vec<_>>();
+    assert_eq!(smart_ids("xps"), vec![2]);
+    // The prefix tier ranks by name key, where the double-spaced copy sorts first.
+    assert_eq!(smart_ids("noteb"), vec![6, 1]);
+
+    catalog.set_dedupe_by_name(false);
+    assert_eq!(ids(catalog.search_ranked("xps", 10)), vec![2, 6]);
+    assert_eq!(ids(catalog.search_by_authority("xps", 10, 1.0, 0.0)), vec![2, 6]);
+    assert_eq!(catalog.search_smart("noteb", 10).0.len(), 3);
+}
+
+#[test]
+fn test_boolean_query_boost_changes_ordering() {
+    let catalog = sample_catalog();
+    let ids = |hits: Vec<(&Product, f32)>| hits.iter().map(|(p, _)| p.id).collect::<Vec<_>>();
+
+    assert_eq!(ids(catalog.search_boolean("dell OR capa", 10).unwrap())[0], 5);
+    assert_eq!(ids(catalog.search_boolean("dell^3 OR capa", 10).unwrap()), vec![2, 1, 5]);
+    assert_eq!(ids(catalog.search_boolean("notebook NOT (dell OR polo)", 10).unwrap()), vec![5]);
+
+    assert_eq!(
+        catalog.parse_query("dell^2.5 notebook"),
+        Ok(QueryNode::And(vec![
+            QueryNode::Boost(Box::new(QueryNode::Term("dell".into())), 2.5),
+            QueryNode::Term("notebook".into()),
+        ]))
+    );
+    let err = catalog.search_boolean("dell^abc", 10).unwrap_err();
+    assert_eq!(err, QueryError::InvalidBoost { term: "dell".into(), value: "abc".into() });
+    assert!(err.to_string().contains("abc"));
+    assert!(matches!(catalog.parse_query("dell^"), Err(QueryError::InvalidBoost { .. })));
+    assert_eq!(catalog.parse_query("(dell"), Err(QueryError::UnbalancedParen));
+
+    assert_eq!(catalog.parse_query("dell NOT"), Err(QueryError::MissingOperand("NOT".into())));
+    assert_eq!(catalog.parse_query("(dell NOT)"), Err(QueryError::MissingOperand("NOT".into())));
+    assert_eq!(catalog.parse_query("OR dell"), Err(QueryError::MissingOperand("OR".into())));
+    assert_eq!(catalog.parse_query("dell OR"), Err(QueryError::MissingOperand("OR".into())));
+    let err = catalog.parse_query("^2 dell").unwrap_err();
+    assert_eq!(err, QueryError::UnexpectedToken("^".into()));
+    assert_eq!(err.to_string(), "unexpected token '^'");
+}
+
+#[test]
+fn test_complementary_and_similar_recommendations() {
+    let mut catalog = sample_catalog();
+    catalog.add_recommendation_edges(&[(1, 2), (1, 5)]);
+    let ids = |ps: Vec<&Product>| ps.iter().map(|p| p.id).collect::<Vec<_>>();
+
+    assert_eq!(ids(catalog.recommend_complementary(1, 5)), vec![5]);
+    assert_eq!(ids(catalog.recommend_similar(1, 5)), vec![2]);
+
+    // The case only has the notebook as a neighbor, so "similar" falls back.
+    assert_eq!(ids(catalog.recommend_similar(5, 5)), vec![1]);
+    assert!(catalog.recommend_complementary(3, 5).is_empty());
+}
+
+#[test]
+fn test_normalization_pipeline_order() {
+    let rules = LanguageRules { stop_words: ["case".to_string()].into_iter().collect(), ..LanguageRules::english() };
+    let config = |pipeline: Vec<NormalizeStep>| {
+        TokenizerConfig { default_language: "en".into(), pipeline, ..Default::default() }.with_language("en", rules.clone())
+    };
+
+    let default = Catalog::with_tokenizer(config(NormalizeStep::DEFAULT_PIPELINE.to_vec()));
+    let stem_first = Catalog::with_tokenizer(config(vec![
+        NormalizeStep::Lowercase,
+        NormalizeStep::Stem,
+        NormalizeStep::RemoveStopWords,
+        NormalizeStep::DropNumbers,
+    ]));
+
+    assert_eq!(default.tokenize("Laptop Cases 15"), vec!["laptop", "case", "15"]);
+    assert_eq!(stem_first.tokenize("Laptop Cases 15"), vec!["laptop"]);
+
+    for mut catalog in [default, stem_first] {
+        catalog.add_product(Product { name: "Laptop Cases".into(), brand: "Acme".into(), category: "Bags".into(), ..Default::default() });
+        assert_eq!(catalog.search_tokens("laptops").len(), 1);
+        assert_eq!(catalog.search_tokens("LAPTOP").len(), 1);
+        // Snippets locate words through the same pipeline, stemming included.
+        assert_eq!(catalog.snippet(1, "laptops", 0).as_deref(), Some("Laptop"));
+    }
+
+    // Listing the step is all it takes to fold accents.
+    let folding = TokenizerConfig { pipeline: vec![NormalizeStep::Lowercase, NormalizeStep::FoldAccents], ..Default::default() };
+    assert!(folding.fold_accents());
+    assert_eq!(Catalog::with_tokenizer(folding).tokenize("Ração"), vec!["racao"]);
+    assert!(!TokenizerConfig::default().fold_accents());
+    assert_eq!(
+        TokenizerConfig::default().with_accent_folding().pipeline,
+        vec![NormalizeStep::Lowercase, NormalizeStep::FoldAccents, NormalizeStep::RemoveStopWords, NormalizeStep::Stem]
+    );
+}
+
+#[derive(Default)]
+struct MemoryStore {
+    snapshot: RefCell<Option<CatalogSnapshot>>,
+}
+
+impl CatalogStore for MemoryStore {
+    fn save_snapshot(&self, snapshot: &CatalogSnapshot) -> Result<(), CatalogError> {
+        *self.snapshot.borrow_mut() = Some(snapshot.clone());
+        Ok(())
+    }
+
+    fn load_snapshot(&self) -> Result<CatalogSnapshot, CatalogError> {
+        self.snapshot.borrow().clone().ok_or_else(|| CatalogError::Invalid { line: 0, reason: "empty store".into() })
+    }
+}
+
+#[test]
+fn test_catalog_store_round_trip() {
+    let mut catalog = sample_catalog();
+    catalog.add_recommendation_edge_with_reason(1, 5, ReasonCode::CoPurchase);
+    catalog.add_recommendation_edge(1, 2);
+    catalog.pin_product("capa", 5);
+    catalog.increment_popularity(4, 7);
+
+    let store = MemoryStore::default();
+    assert!(Catalog::new().load_from(&store).is_err());
+    catalog.save_to(&store).unwrap();
+
+    let mut restored = Catalog::new();
+    restored.load_from(&store).unwrap();
+    restored.check_invariants();
+    assert_eq!(restored.snapshot(), catalog.snapshot());
+    assert_eq!(restored.search_tokens("notebook").len(), 3);
+    assert_eq!(restored.graph().edge_reasons(1, 5), vec![ReasonCode::CoPurchase]);
+    assert_eq!(restored.popularity(4), 7);
+
+    restored.add_product(product("Mouse Gamer", "Logitech", "Eletrônicos", "RGB"));
+    assert!(restored.get_product(6).is_some());
+
+    let path = std::env::temp_dir().join(format!("catalog-store-{}.json", std::process::id()));
+    let file = FileStore::new(&path);
+    catalog.save_to(&file).unwrap();
+    let mut from_file = Catalog::new();
+    from_file.load_from(&file).unwrap();
+    assert_eq!(from_file.snapshot(), catalog.snapshot());
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(from_file.load_from(&file), Err(CatalogError::Io(_))));
+}
+
+#[test]
+fn test_recommend_pages_respect_exclusions() {
+    let mut catalog = Catalog::new();
+    for i in 0..9 {
+        catalog.add_product(product(&format!("Item {}", i), "Marca", "Casa", ""));
+    }
+    catalog.add_recommendation_edges(&(2..=9).map(|id| (1, id)).collect::<Vec<_>>());
+    catalog.add_recommendation_edges(&[(2, 3), (2, 4), (5, 6)]);
+
+    let exclude: HashSet<usize> = [3, 6, 8].into_iter().collect();
+    let filtered: Vec<usize> = catalog.graph().ranked_neighbors(1, RecommendMode::Degree).into_iter()
+        .filter(|id| !exclude.contains(id))
+        .collect();
+
+    let mut paged = Vec::new();
+    for offset in (0..10).step_by(2) {
+        let page = catalog.recommend_page_excluding(1, offset, 2, &exclude);
+        assert_eq!(page.len(), filtered.len().saturating_sub(offset).min(2));
+        paged.extend(page.iter().map(|p| p.id));
+    }
+    assert_eq!(paged, filtered);
+    assert_eq!(filtered.len(), 5);
+}
+
+#[test]
+fn test_keywords_favor_distinctive_terms() {
+    let mut catalog = Catalog::new();
+    for brand in ["Dell", "Acer", "Asus", "Lenovo"] {
+        catalog.add_product(product("Notebook", brand, "Eletrônicos", "Tela grande"));
+    }
+    catalog.add_product(product("Camiseta", "MarcaX", "Vestuário", "Algodão"));
+
+    let keywords = catalog.keywords(4, 3);
+    assert_eq!(keywords.len(), 3);
+    assert_eq!(keywords[0].0, "lenovo");
+    let score = |term: &str| catalog.keywords(4, 10).into_iter().find(|(t, _)| t == term).map(|(_, s)| s).unwrap();
+    assert!(score("lenovo") > score("eletrônicos"));
+    assert!(catalog.keywords(99, 3).is_empty());
+}
+
+#[test]
+fn test_proportional_fuzzy_distance_scales_with_length() {
+    let catalog = sample_catalog();
+    let proportional = FuzzyDistance::Proportional { factor: 5 };
+    let ids = |ps: Vec<&Product>| ps.iter().map(|p| p.id).collect::<Vec<_>>();
+
+    assert_eq!(proportional.max_for("portabilidade"), 2);
+    assert_eq!(proportional.max_for("dell"), 0);
+    assert_eq!(ids(catalog.search_fuzzy_with("pertabilidadi", proportional)), vec![2]);
+    assert!(catalog.search_fuzzy_with("dwll", proportional).is_empty());
+
+    assert_eq!(ids(catalog.search_fuzzy("dwll", 1)), vec![1, 2]);
+    assert!(catalog.search_fuzzy("pertabilidadi", 1).is_empty());
+}
+
+#[test]
+fn test_term_dictionary_tracks_mutations() {
+    let mut catalog = sample_catalog();
+    let dictionary = catalog.term_dictionary();
+    assert!(dictionary.windows(2).all(|w| w[0] < w[1]));
+    assert!(dictionary.contains(&"golden"));
+    assert!(!dictionary.contains(&"logitech"));
+
+    catalog.add_product(product("Mouse Gamer", "Logitech", "Eletrônicos", "RGB"));
+    assert!(catalog.term_dictionary().contains(&"logitech"));
+
+    catalog.remove_product(4);
+    assert!(!catalog.term_dictionary().contains(&"golden"));
+    assert!(catalog.term_dictionary().contains(&"e"));
+
+    catalog.update_product(6, product("Teclado Gamer", "Redragon", "Eletrônicos", "RGB"));
+    assert!(!catalog.term_dictionary().contains(&"logitech"));
+    assert!(catalog.term_dictionary().contains(&"redragon"));
+    catalog.check_invariants();
+}
+
+#[test]
+fn test_bayesian_rating_outranks_single_review() {
+    let mut catalog = Catalog::new();
+    catalog.add_product(Product { rating: 5.0, rating_count: 1, ..product("Fone Bluetooth A", "Sony", "Áudio", "") });
+    catalog.add_product(Product { rating: 4.5, rating_count: 500, ..product("Fone Bluetooth B", "Sony", "Áudio", "") });
+
+    let ids = |hits: Vec<(&Product, f32)>| hits.iter().map(|(p, _)| p.id).collect::<Vec<_>>();
+    assert!(catalog.bayesian_rating(2).unwrap() > catalog.bayesian_rating(1).unwrap());
+    assert_eq!(ids(catalog.search_rated("fone bluetooth", 10)), vec![2, 1]);
+
+    // Without a prior the raw average wins.
+    catalog.set_rating_config(RatingConfig { prior_weight: 0.0, ..Default::default() });
+    assert_eq!(catalog.bayesian_rating(1), Some(5.0));
+    assert_eq!(ids(catalog.search_rated("fone bluetooth", 10)), vec![1, 2]);
+}
+
+#[test]
+fn test_category_index_follows_mutations() {
+    let mut catalog = sample_catalog();
+    let ids = |ps: Vec<&Product>| ps.iter().map(|p| p.id).collect::<Vec<_>>();
+    assert_eq!(ids(catalog.products_in_category("eletrônicos")), vec![1, 2]);
+
+    catalog.add_product(product("Mouse Gamer", "Logitech", "Eletrônicos", "RGB"));
+    assert_eq!(ids(catalog.products_in_category("Eletrônicos")), vec![1, 2, 6]);
+
+    catalog.update_product(2, product("Notebook Dell XPS 13", "Dell", "Acessórios", ""));
+    assert_eq!(ids(catalog.products_in_category("Eletrônicos")), vec![1, 6]);
+    assert_eq!(ids(catalog.products_in_category("Acessórios")), vec![2, 5]);
+
+    catalog.remove_product(4);
+    assert!(catalog.products_in_category("Pet Shop").is_empty());
+    catalog.check_invariants();
+}
+
+#[test]
+fn test_ordered_backends_agree() {
+    let mut btree = BTreeIndex::default();
+    let mut sorted = SortedVecIndex::default();
+    let keys = ["capa", "camiseta", "notebook dell", "notebook acer", "mouse", "notebook dell", "cadeira"];
+    for (id, key) in keys.iter().enumerate() {
+        btree.insert(key, id);
+        sorted.insert(key, id);
+    }
+    btree.remove("mouse", 4);
+    sorted.remove("mouse", 4);
+
+    for prefix in ["", "ca", "notebook", "notebook d", "x"] {
+        assert_eq!(btree.prefix(prefix, 10), sorted.prefix(prefix, 10), "prefix {prefix:?}");
+        assert_eq!(btree.prefix(prefix, 2), sorted.prefix(prefix, 2), "prefix {prefix:?}");
+    }
+    for (from, to) in [("c", "n"), ("cam", "capa"), ("a", "z"), ("n", "c"), ("mouse", "mousf")] {
+        assert_eq!(btree.range(from, to, 10), sorted.range(from, to, 10), "range {from:?}..{to:?}");
+    }
+    assert_eq!(sorted.prefix("notebook", 10), vec![3, 2, 5]);
+
+    let catalogs = [OrderedBackend::BTree, OrderedBackend::SortedVec].map(|backend| {
+        let mut catalog = Catalog::with_ordered_backend(backend);
+        for p in ["Notebook Dell", "Notebook Acer", "Capa", "Mouse"] {
+            catalog.add_product(product(p, "Marca", "Casa", ""));
+        }
+        catalog.remove_product(4);
+        catalog
+    });
+    let names = |ps: Vec<&Product>| ps.iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+    assert_eq!(names(catalogs[1].search_prefix_ordered("note", 10)), vec!["Notebook Acer", "Notebook Dell"]);
+    assert_eq!(names(catalogs[0].search_name_range("C", "O", 10)), vec!["Capa", "Notebook Acer", "Notebook Dell"]);
+    assert_eq!(names(catalogs[0].search_name_range("a", "z", 10)), names(catalogs[1].search_name_range("a", "z", 10)));
+
+    // The backend also holds the term dictionary behind prefix and fuzzy matching.
+    assert_eq!(catalogs[0].term_dictionary(), catalogs[1].term_dictionary());
+    assert!(!catalogs[1].term_dictionary().contains(&"mouse"));
+    for catalog in &catalogs {
+        let tiered: Vec<(usize, SearchTier)> = catalog.search_tiered("note", 10).iter().map(|(p, t)| (p.id, *t)).collect();
+        assert_eq!(tiered, vec![(1, SearchTier::Prefix), (2, SearchTier::Prefix)]);
+        catalog.check_invariants();
+    }
+}
+
+#[test]
+fn test_most_similar_to_tokens() {
+    let catalog = sample_catalog();
+    let tokens: Vec<String> = ["Notebook", "DELL", "eletrônicos", "usado"].into_iter().map(String::from).collect();
+
+    let similar = catalog.most_similar_to_tokens(&tokens, 3);
+    let mut top: Vec<usize> = similar[..2].iter().map(|(p, _)| p.id).collect();
+    top.sort_unstable();
+    assert_eq!(top, vec![1, 2]);
+    assert_eq!(similar[2].0.id, 5);
+    assert!(similar[1].1 > similar[2].1);
+    assert!(catalog.most_similar_to_tokens(&["inexistente".to_string()], 3).is_empty());
+}
+
+#[test]
+fn test_recommend_diagnostic_empty_graph() {
+    let catalog = sample_catalog();
+    assert_eq!(catalog.recommend_diagnosed(1, 5), (vec![], Some(RecommendDiagnostic::GraphEmpty)));
+}
+
+#[test]
+fn test_recommend_diagnostic_missing_and_isolated_seed() {
+    let mut catalog = sample_catalog();
+    catalog.add_recommendation_edge(1, 2);
+
+    assert_eq!(catalog.recommend_diagnosed(42, 5).1, Some(RecommendDiagnostic::SeedMissing));
+    assert_eq!(catalog.recommend_diagnosed(3, 5).1, Some(RecommendDiagnostic::SeedIsolated));
+
+    let (results, diagnostic) = catalog.recommend_diagnosed(1, 5);
+    assert_eq!(results.iter().map(|p| p.id).collect::<Vec<_>>(), vec![2]);
+    assert_eq!(diagnostic, None);
+
+    catalog.update_product(2, Product { recommendable: false, ..product("Notebook Dell XPS 13", "Dell", "Eletrônicos", "") });
+    assert_eq!(catalog.recommend_diagnosed(1, 5).1, Some(RecommendDiagnostic::NoCandidates));
+}
+
+#[test]
+fn test_promotion_boost_toggles_ordering() {
+    let mut catalog = sample_catalog();
+    catalog.update_product(5, Product { on_promotion: true, ..product("Capa para Notebook 15", "AcessoriosPro", "Acessórios", "Resistente à água") });
+    catalog.add_recommendation_edges(&[(1, 2), (1, 5), (2, 3), (3, 5)]);
+    let ids = |hits: Vec<(&Product, f32)>| hits.iter().map(|(p, _)| p.id).collect::<Vec<_>>();
+    let rec_ids = |ps: Vec<&Product>| ps.iter().map(|p| p.id).collect::<Vec<_>>();
+
+    let baseline = ids(catalog.search_ranked("notebook", 10));
+    assert_ne!(baseline[0], 5);
+    assert_eq!(rec_ids(catalog.recommend_for(1, 5)), vec![2, 5]);
+    assert_eq!(rec_ids(catalog.recommend_by_reason(1, 5, &[ReasonCode::Manual])), vec![2, 5]);
+    assert_eq!(rec_ids(catalog.recommend_multi_hop(3, 2, 5)), vec![2, 5, 1]);
+
+    catalog.set_promotion_multiplier(10.0);
+    catalog.set_promote_sales(true);
+    let promoted = ids(catalog.search_ranked("notebook", 10));
+    assert_eq!(promoted[0], 5);
+    assert_eq!(promoted.len(), baseline.len());
+    assert!(!ids(catalog.search_ranked("dell", 10)).contains(&5));
+    assert_eq!(rec_ids(catalog.recommend_for(1, 5)), vec![5, 2]);
+    assert_eq!(rec_ids(catalog.recommend_by_reason(1, 5, &[ReasonCode::Manual])), vec![5, 2]);
+    // Promotion reorders within a hop but never pulls a farther hop forward.
+    assert_eq!(rec_ids(catalog.recommend_multi_hop(3, 2, 5)), vec![5, 2, 1]);
+    assert_eq!(rec_ids(catalog.recommend_multi_hop(2, 2, 5)), vec![1, 3, 5]);
+
+    catalog.set_promote_sales(false);
+    assert_eq!(ids(catalog.search_ranked("notebook", 10)), baseline);
+    assert_eq!(rec_ids(catalog.recommend_for(1, 5)), vec![2, 5]);
+}
+
+#[test]
+fn test_forward_term_map_stays_in_sync() {
+    let mut catalog = sample_catalog();
+    catalog.check_invariants();
+    assert_eq!(catalog.product_terms(4), vec!["adulto", "e", "golden", "pet", "proteína", "ração", "shop", "vitaminas"]);
+
+    catalog.add_product(product("Mouse Gamer", "Logitech", "Eletrônicos", "RGB"));
+    catalog.update_product(2, product("Notebook Dell XPS 15", "Dell", "Eletrônicos", "OLED"));
+    catalog.remove_product(3);
+    catalog.check_invariants();
+
+    assert!(catalog.product_terms(2).contains(&"oled".to_string()));
+    assert!(!catalog.product_terms(2).contains(&"13".to_string()));
+    assert!(catalog.product_terms(3).is_empty());
+    assert_eq!(catalog.product_terms(6), vec!["eletrônicos", "gamer", "logitech", "mouse", "rgb"]);
+}
+
+#[test]
+fn test_best_weighted_path_prefers_stronger_route() {
+    let mut graph = RecGraph::new();
+    graph.add_edges(&[(1, 2), (2, 4)]);
+    graph.add_weighted_edge(1, 3, 4.0);
+    graph.add_weighted_edge(3, 4, 4.0);
+    graph.add_edge(5, 6);
+
+    let (path, score) = graph.best_weighted_path(1, 4).unwrap();
+    assert_eq!(path, vec![1, 3, 4]);
+    assert!((score - 2.0).abs() < 1e-6);
+
+    graph.add_weighted_edge(1, 3, 0.5);
+    let (path, score) = graph.best_weighted_path(4, 1).unwrap();
+    assert_eq!(path, vec![4, 2, 1]);
+    assert!((score - 0.5).abs() < 1e-6);
+
+    assert_eq!(graph.best_weighted_path(1, 5), None);
+    assert_eq!(graph.best_weighted_path(1, 1), None);
+    assert_eq!(graph.edge_weight(2, 1), Some(1.0));
+}
+
+#[test]
+fn test_search_clustered_by_brand() {
+    let mut catalog = sample_catalog();
+    catalog.add_product(product("Suporte Notebook", "", "Acessórios", ""));
+
+    let ranked: Vec<usize> = catalog.search_ranked("notebook", 10).iter().map(|(p, _)| p.id).collect();
+    let clusters = catalog.search_clustered("notebook", Field::Brand);
+    let shape: Vec<(&str, Vec<usize>)> = clusters.iter()
+        .map(|(key, ps)| (key.as_str(), ps.iter().map(|p| p.id).collect()))
+        .collect();
+
+    assert_eq!(shape[0].0, "Dell");
+    let dell: Vec<usize> = ranked.iter().copied().filter(|id| [1, 2].contains(id)).collect();
+    assert_eq!(shape[0].1, dell);
+    assert_eq!(shape.len(), 3);
+    assert!(shape[1..].contains(&("AcessoriosPro", vec![5])));
+    assert!(shape[1..].contains(&("(none)", vec![6])));
+    assert!(catalog.search_clustered("inexistente", Field::Category).is_empty());
+}
+
+#[test]
+fn test_explain_score_sums_to_ranked_score() {
+    let mut catalog = sample_catalog();
+    catalog.update_product(2, Product { on_promotion: true, ..product("Notebook Dell XPS 13", "Dell", "Eletrônicos", "Performance e portabilidade") });
+    catalog.set_promote_sales(true);
+
+    let results = catalog.search_ranked("notebook dell", 10);
+    assert_eq!(results.len(), 2);
+    for (p, score) in results {
+        let breakdown = catalog.explain_score(p.id, "notebook dell");
+        let sum: f32 = breakdown.terms.iter().map(|t| t.score).sum::<f32>() + breakdown.promotion_boost;
+        assert!((breakdown.total - score).abs() < 1e-5);
+        assert!((sum - breakdown.total).abs() < 1e-5);
+        assert_eq!(breakdown.terms.iter().map(|t| t.term.as_str()).collect::<Vec<_>>(), vec!["notebook", "dell"]);
+        assert_eq!(breakdown.promotion_boost > 0.0, p.id == 2);
+    }
+    assert_eq!(catalog.explain_score(99, "notebook").total, 0.0);
+}
+
+#[test]
+fn test_compact_remaps_ids() {
+    let mut catalog = sample_catalog();
+    catalog.add_product(product("Mouse Gamer", "Logitech", "Eletrônicos", "RGB"));
+    catalog.add_recommendation_edges(&[(2, 5), (5, 6), (1, 3)]);
+    catalog.pin_product("mouse", 6);
+    catalog.increment_popularity(6, 3);
+    catalog.remove_product(1);
+    catalog.remove_product(4);
+
+    let mapping = catalog.compact();
+    assert_eq!(mapping.len(), 4);
+    assert_eq!((mapping[&2], mapping[&3], mapping[&5], mapping[&6]), (1, 2, 3, 4));
+    catalog.check_invariants();
+
+    let mut notebooks: Vec<usize> = catalog.search_tokens("notebook").iter().map(|p| p.id).collect();
+    notebooks.sort_unstable();
+    assert_eq!(notebooks, vec![1, 3]);
+    assert_eq!(catalog.get_product(4).unwrap().name, "Mouse Gamer");
+    assert_eq!(catalog.graph().neighbors(3), vec![1, 4]);
+    assert!(catalog.graph().neighbors(2).is_empty());
+    assert_eq!(catalog.search_ranked("mouse", 5)[0].0.id, 4);
+    assert_eq!(catalog.popularity(4), 3);
+
+    catalog.add_product(product("Teclado", "Redragon", "Eletrônicos", ""));
+    assert!(catalog.get_product(5).is_some());
+}
+
+#[test]
+fn test_empty_query_policy_empty_and_error() {
+    let mut catalog = sample_catalog();
+    let result = catalog.search("  ", 10);
+    assert!(result.products.is_empty());
+    assert_eq!(result.total, 0);
+
+    assert_eq!(catalog.search_with_policy("?!", 10, Some(EmptyQueryPolicy::Error)).unwrap_err(), QueryError::Empty);
+    assert_eq!(catalog.search_with_policy("notebook", 10, Some(EmptyQueryPolicy::Error)).unwrap().total, 3);
+    assert!(catalog.search_with_policy("", 10, None).unwrap().products.is_empty());
+
+    catalog.set_empty_query_policy(EmptyQueryPolicy::Error);
+    assert!(catalog.search("", 10).products.is_empty());
+    assert_eq!(catalog.search_with_policy("", 10, None).unwrap_err(), QueryError::Empty);
+    assert_eq!(catalog.search_with_policy("", 10, Some(EmptyQueryPolicy::All)).unwrap().total, 5);
+}
+
+#[test]
+fn test_empty_query_policy_all_respects_limit() {
+    let mut catalog = sample_catalog();
+    catalog.set_empty_query_policy(EmptyQueryPolicy::All);
+
+    let result = catalog.search("", 3);
+    assert_eq!(result.products.iter().map(|p| p.id).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(result.total, 5);
+    assert!(result.truncated);
+    assert_eq!(catalog.search("notebook", 10).total, 3);
+}
+
+#[test]
+fn test_precomputed_similarity_matches_on_the_fly() {
+    let mut catalog = sample_catalog();
+    catalog.add_product(product("Mouse Dell", "Dell", "Eletrônicos", "Sem fio"));
+    let ids = |ps: Vec<&Product>| ps.iter().map(|p| p.id).collect::<Vec<_>>();
+
+    catalog.precompute_similarity(3);
+    assert!(catalog.has_precomputed_similarity());
+    for id in 1..=6 {
+        assert_eq!(ids(catalog.recommend_precomputed(id, 3)), ids(catalog.similar_products(id, 3)), "product {id}");
+        assert_eq!(ids(catalog.recommend_precomputed(id, 5)), ids(catalog.similar_products(id, 5)), "product {id}");
+    }
+    assert_eq!(ids(catalog.recommend_precomputed(1, 1)), vec![2]);
+
+    catalog.remove_product(2);
+    assert!(catalog.has_precomputed_similarity());
+    assert!(!ids(catalog.recommend_precomputed(1, 3)).contains(&2));
+
+    // Compaction renumbers ids, so the matrix is rebuilt under the new ids.
+    catalog.compact();
+    assert!(catalog.has_precomputed_similarity());
+    assert_eq!(catalog.similarity_rows_computed(), 5);
+    for id in 1..=5 {
+        assert_eq!(ids(catalog.recommend_precomputed(id, 3)), ids(catalog.similar_products(id, 3)), "product {id}");
+    }
+}
+
+#[test]
+fn test_negated_query_terms() {
+    let catalog = sample_catalog();
+    let names = |ps: Vec<&Product>| ps.iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+    let scored_ids = |hits: Vec<(&Product, f32)>| hits.iter().map(|(p, _)| p.id).collect::<Vec<_>>();
+
+    assert_eq!(names(catalog.search_tokens("notebook dell -xps")), vec!["Notebook Dell Inspiron 15"]);
+    assert_eq!(scored_ids(catalog.search_ranked("notebook -xps -capa", 10)), vec![1]);
+    assert_eq!(scored_ids(catalog.search_tokens_scored("notebook -xps -capa", 10)), vec![1]);
+    let or_ids = scored_ids(catalog.search_or_scored("notebook polo -xps", 10));
+    assert!(or_ids.contains(&3) && !or_ids.contains(&2));
+    assert_eq!(scored_ids(catalog.search_rated("notebook -xps -capa", 10)), vec![1]);
+    assert_eq!(scored_ids(catalog.search_by_authority("notebook -xps -capa", 10, 1.0, 1.0)), vec![1]);
+    let collapsed: Vec<usize> = catalog.search_collapsed("notebook -xps", 10).iter().map(|(p, _)| p.id).collect();
+    assert!(!collapsed.contains(&2));
+    let clusters = catalog.search_clustered("notebook -xps", Field::Brand);
+    assert!(clusters.iter().flat_map(|(_, ps)| ps).all(|p| p.id != 2));
+    let (smart, _) = catalog.search_smart("notebook -xps -capa", 10);
+    assert_eq!(names(smart), vec!["Notebook Dell Inspiron 15"]);
+    assert_eq!(catalog.explain_score(1, "notebook -xps").total, catalog.explain_score(1, "notebook").total);
+
+    let ids = |ps: Vec<&Product>| ps.iter().map(|p| p.id).collect::<Vec<_>>();
+    assert_eq!(ids(catalog.search_relaxed("notebook -xps", 10).0), vec![1, 5]);
+    assert_eq!(catalog.search_tiered("notebook -xps", 10).iter().map(|(p, _)| p.id).collect::<Vec<_>>(), vec![1, 5]);
+    let mut in_fields = ids(catalog.search_in_fields("notebook -xps", &[Field::Name]));
+    in_fields.sort_unstable();
+    assert_eq!(in_fields, vec![1, 5]);
+    // The name-prefix fallback only sees the positive words.
+    assert_eq!(ids(catalog.search_smart("noteb", 10).0), vec![1, 2]);
+    assert_eq!(ids(catalog.search_smart("noteb -xps", 10).0), vec![1]);
+
+    // Only negated words: nothing positive to match.
+    assert!(catalog.search_tokens("-xps").is_empty());
+    // A hyphen inside a word is not a negation.
+    assert_eq!(catalog.search_tokens("notebook-dell").len(), 2);
+}
+
+#[test]
+fn test_targeted_edge_mutation_invalidates_only_dependent_recommendations() {
+    let mut catalog = sample_catalog();
+    catalog.add_product(product("Mouse Gamer", "Logitech", "Eletrônicos", "RGB"));
+    catalog.add_recommendation_edges(&[(1, 2), (1, 5), (3, 4)]);
+    let ids = |ps: Vec<&Product>| ps.iter().map(|p| p.id).collect::<Vec<_>>();
+
+    assert_eq!(ids(catalog.recommend_for(1, 5)), vec![2, 5]);
+    assert_eq!(ids(catalog.recommend_for(3, 5)), vec![4]);
+    assert_eq!(catalog.recommendation_cache_len(), 2);
+
+    // 5 gains a neighbor, so its degree now outranks 2 in seed 1's list.
+    catalog.add_recommendation_edge(5, 6);
+    assert_eq!(catalog.recommendation_cache_len(), 1);
+    assert_eq!(ids(catalog.recommend_for(1, 5)), vec![5, 2]);
+    assert_eq!(ids(catalog.recommend_for(3, 5)), vec![4]);
+    assert_eq!(catalog.recommendation_cache_len(), 2);
+
+    // Removing 6 lowers 5's degree again: a two-hop dependency of seed 1.
+    catalog.remove_product(6);
+    assert_eq!(catalog.recommendation_cache_len(), 1);
+    assert_eq!(ids(catalog.recommend_for(1, 5)), vec![2, 5]);
+
+    catalog.remove_recommendation_edge(3, 4);
+    assert_eq!(catalog.recommendation_cache_len(), 1);
+    assert!(catalog.recommend_for(3, 5).is_empty());
+}
+
+#[test]
+fn test_targeted_product_mutation_invalidates_only_dependent_entries() {
+    let mut catalog = sample_catalog();
+    catalog.add_recommendation_edges(&[(1, 2), (3, 4)]);
+    let ids = |ps: Vec<&Product>| ps.iter().map(|p| p.id).collect::<Vec<_>>();
+    catalog.recommend_for(1, 5);
+    catalog.recommend_for(3, 5);
+
+    catalog.update_product(2, Product { recommendable: false, ..product("Notebook Dell XPS 13", "Dell", "Eletrônicos", "Performance e portabilidade") });
+    assert_eq!(catalog.recommendation_cache_len(), 1);
+    assert!(catalog.recommend_for(1, 5).is_empty());
+
+    catalog.precompute_similarity(3);
+    let computed = catalog.similarity_rows_computed();
+    assert_eq!(computed, 5);
+
+    // The shirt shares no terms with anything, before or after the edit.
+    catalog.update_product(3, product("Camiseta Regata", "MarcaY", "Moda", "Linho"));
+    assert_eq!(catalog.similarity_rows_computed(), computed + 1);
+
+    // Renaming the pet food into a notebook touches every notebook row.
+    catalog.update_product(4, product("Notebook Acer", "Acer", "Eletrônicos", "Intel"));
+    assert!(catalog.similarity_rows_computed() > computed + 1);
+    for id in 1..=5 {
+        assert_eq!(ids(catalog.recommend_precomputed(id, 3)), ids(catalog.similar_products(id, 3)), "product {id}");
+    }
+    assert!(ids(catalog.recommend_precomputed(1, 3)).contains(&4));
+}
@@ -0,0 +1,84 @@
+#![cfg(feature = "tracing")]
+
+use search_system::{Catalog, Product};
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::Registry;
+
+type Fields = Arc<Mutex<Vec<(String, String)>>>;
+
+struct Capture(Fields);
+
+struct Recorder<'a>(&'a mut Vec<(String, String)>);
+
+impl Visit for Recorder<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.push((field.name().to_string(), format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name().to_string(), value.to_string()));
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Capture {
+    fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, _: &tracing::span::Id, _: Context<'_, S>) {
+        attrs.record(&mut Recorder(&mut self.0.lock().unwrap()));
+    }
+
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        event.record(&mut Recorder(&mut self.0.lock().unwrap()));
+    }
+}
+
+#[test]
+fn test_search_emits_tracing_fields() {
+    let mut catalog = Catalog::new();
+    for name in ["Notebook Dell Inspiron", "Notebook Dell XPS", "Mouse Dell"] {
+        catalog.add_product(Product { name: name.into(), brand: "Dell".into(), category: "Eletrônicos".into(), ..Default::default() });
+    }
+
+    let fields: Fields = Arc::default();
+    let subscriber = Registry::default().with(Capture(fields.clone()));
+    let result = tracing::subscriber::with_default(subscriber, || catalog.search("notebook dell", 1));
+    assert_eq!(result.total, 2);
+
+    let fields = fields.lock().unwrap();
+    let get = |name: &str| fields.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+    assert_eq!(get("query"), Some("notebook dell"));
+    assert_eq!(get("limit"), Some("1"));
+    assert_eq!(get("tokens"), Some("2"));
+    assert_eq!(get("results"), Some("2"));
+    assert!(get("elapsed_us").is_some());
+}
+
+#[test]
+fn test_indexing_and_token_search_emit_tracing_fields() {
+    let mut catalog = Catalog::new();
+    let fields: Fields = Arc::default();
+    let subscriber = Registry::default().with(Capture(fields.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        catalog.add_product(Product { name: "Notebook Dell".into(), brand: "Dell".into(), category: "Eletrônicos".into(), ..Default::default() });
+    });
+    {
+        let fields = fields.lock().unwrap();
+        let get = |name: &str| fields.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+        assert_eq!(get("id"), Some("1"));
+        assert_eq!(get("tokens"), Some("3"));
+        assert!(get("elapsed_us").is_some());
+    }
+
+    fields.lock().unwrap().clear();
+    let subscriber = Registry::default().with(Capture(fields.clone()));
+    let results = tracing::subscriber::with_default(subscriber, || catalog.search_tokens("notebook -xps"));
+    assert_eq!(results.len(), 1);
+
+    let fields = fields.lock().unwrap();
+    let get = |name: &str| fields.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+    assert_eq!(get("query"), Some("notebook -xps"));
+    assert_eq!(get("tokens"), Some("1"));
+    assert_eq!(get("results"), Some("1"));
+    assert!(get("elapsed_us").is_some());
+}